@@ -0,0 +1,376 @@
+// The computer controlled opponent
+
+use vec2::*;
+use physics::*;
+use random::*;
+use game::*;
+use controller::*;
+
+pub enum AiLevel { Beginner, Club, Pro, Unfair }
+
+// Classic only reacts to where the puck is now, Predictive looks ahead
+pub enum AiStyle { Classic, Predictive }
+
+pub fn allAiLevels() -> ~[AiLevel] {
+    ~[Beginner, Club, Pro, Unfair]
+}
+
+pub fn aiLevelName(level: AiLevel) -> ~str {
+    match level {
+        Beginner => ~"beginner",
+        Club => ~"club",
+        Pro => ~"pro",
+        Unfair => ~"unfair"
+    }
+}
+
+pub fn aiLevelFromName(name: &str) -> Option<AiLevel> {
+    for allAiLevels().each |&level| {
+        if aiLevelName(level) == name.to_owned() {
+            return Some(level);
+        }
+    }
+    None
+}
+
+pub fn aiStyleName(style: AiStyle) -> ~str {
+    match style {
+        Classic => ~"classic",
+        Predictive => ~"predictive"
+    }
+}
+
+pub fn aiStyleFromName(name: &str) -> Option<AiStyle> {
+    match name {
+        "classic" => Some(Classic),
+        "predictive" => Some(Predictive),
+        _ => None
+    }
+}
+
+// Personality of the opponent. Speeds are in units per second.
+pub struct AiProfile {
+    // Speed while guarding the goal or approaching a slow puck
+    defenceSpeed: f64,
+    // Speed of a dash towards the puck
+    attackSpeed: f64,
+    // Only dash when the puck is closer than this
+    attackRadius: f64,
+    // Seconds it takes for the opponent to notice what the puck does
+    reactionDelay: f64,
+    // Largest distance between where the opponent aims and where it should aim
+    aimNoise: f64,
+    // Seconds before the opponent picks a new aiming error
+    aimNoiseInterval: f64,
+    // In units per second squared
+    maxAcceleration: f64
+}
+
+pub fn aiProfile(level: AiLevel) -> AiProfile {
+    match level {
+        Beginner => AiProfile {
+            defenceSpeed: 120.,
+            attackSpeed: 350.,
+            attackRadius: 80.,
+            reactionDelay: 0.25,
+            aimNoise: 40.,
+            aimNoiseInterval: 0.5,
+            maxAcceleration: 1500.
+        },
+        // The original opponent
+        Club => AiProfile {
+            defenceSpeed: 180.,
+            attackSpeed: 600.,
+            attackRadius: 100.,
+            reactionDelay: 0.,
+            aimNoise: 0.,
+            aimNoiseInterval: 0.5,
+            maxAcceleration: f64::infinity
+        },
+        Pro => AiProfile {
+            defenceSpeed: 260.,
+            attackSpeed: 900.,
+            attackRadius: 120.,
+            reactionDelay: 0.,
+            aimNoise: 0.,
+            aimNoiseInterval: 0.5,
+            maxAcceleration: f64::infinity
+        },
+        Unfair => AiProfile {
+            defenceSpeed: 450.,
+            attackSpeed: 1500.,
+            attackRadius: 150.,
+            reactionDelay: 0.,
+            aimNoise: 0.,
+            aimNoiseInterval: 0.5,
+            maxAcceleration: f64::infinity
+        }
+    }
+}
+
+// The game as the AI sees it. For the left side everything is mirrored,
+// so that the AI can always assume it is defending the goal on the right.
+struct View {
+    position: Vec2,
+    velocity: Vec2,
+    // Position of the paddle on the other side
+    other: Vec2,
+    puckPosition: Vec2,
+    puckVelocity: Vec2,
+    aimOffset: Vec2,
+    // Middle of the mouth of the goal the AI defends, and of the one it shoots at
+    ownGoal: Vec2,
+    targetGoal: Vec2,
+    // The side the AI plays on, to mirror positions back
+    side: Side
+}
+
+fn mirrorPosition(game:&Game, side:Side, v:Vec2) -> Vec2 {
+    match side {
+        LeftSide => Vec2(game.field.x - v.x, v.y),
+        RightSide => v
+    }
+}
+
+fn mirrorVelocity(side:Side, v:Vec2) -> Vec2 {
+    match side {
+        LeftSide => Vec2(-v.x, v.y),
+        RightSide => v
+    }
+}
+
+pub struct AiController {
+    profile: AiProfile,
+    style: AiStyle,
+    rng: Rng,
+    // Position and velocity of the puck during the last steps, oldest first and already mirrored
+    observations: ~[(Vec2, Vec2)],
+    aimOffset: Vec2,
+    // Seconds until a new aiming error is picked
+    aimTimer: f64
+}
+
+pub fn AiController(level: AiLevel, style: AiStyle, seed: u64) -> AiController {
+    AiController {
+        profile: aiProfile(level),
+        style: style,
+        rng: Rng(seed),
+        observations: ~[],
+        aimOffset: Zero,
+        aimTimer: 0.
+    }
+}
+
+fn between<T:Ord>(x:T, a:T, b:T) -> bool { x > a && x < b }
+fn distance(a:Vec2,b:Vec2) -> f64 { (a-b).length() }
+
+pub impl AiController {
+    // Remembers the current state of the puck and returns the one the AI is reacting to
+    fn observePuck(&mut self, position:Vec2, velocity:Vec2, dt:f64) -> (Vec2, Vec2) {
+        let delaySteps = (self.profile.reactionDelay / dt) as uint;
+        self.observations.push((position, velocity));
+        while self.observations.len() > delaySteps + 1 {
+            self.observations.shift();
+        }
+        self.observations[0]
+    }
+
+    fn updateAim(&mut self, dt:f64) {
+        if self.profile.aimNoise <= 0. { return; }
+        self.aimTimer -= dt;
+        if self.aimTimer <= 0. {
+            self.aimTimer = self.profile.aimNoiseInterval;
+            // Pick a random point within a circle, without trigonometry so that the simulation stays deterministic
+            let mut offset = Vec2(self.rng.range(-1., 1.), self.rng.range(-1., 1.));
+            while offset.length2() > 1. {
+                offset = Vec2(self.rng.range(-1., 1.), self.rng.range(-1., 1.));
+            }
+            self.aimOffset = offset * self.profile.aimNoise;
+        }
+    }
+}
+
+impl Controller for AiController {
+    fn control(&mut self, game: &Game, side: Side, _: &Devices) -> Command {
+        let dt = TimeStep;
+        let paddle = game.paddle(side);
+        let (puckPosition, puckVelocity) = self.observePuck(
+            mirrorPosition(game, side, game.puck.position), mirrorVelocity(side, game.puck.velocity), dt);
+        self.updateAim(dt);
+
+        let view = View {
+            position: mirrorPosition(game, side, paddle.position),
+            velocity: mirrorVelocity(side, paddle.velocity),
+            other: mirrorPosition(game, side, game.paddle(opposite(side)).position),
+            puckPosition: puckPosition,
+            puckVelocity: puckVelocity,
+            aimOffset: self.aimOffset,
+            ownGoal: mirrorPosition(game, side, game.goal(side).center),
+            targetGoal: mirrorPosition(game, side, game.goal(opposite(side)).center),
+            side: side
+        };
+        let desiredVelocity = match self.style {
+            Classic => classicVelocity(game, &self.profile, &view),
+            Predictive => predictiveVelocity(game, &self.profile, &view)
+        };
+
+        // Limit how quickly the AI can change its velocity
+        let velocity = approach(view.velocity, desiredVelocity, dt, game.physics.aiResponse);
+        let change = velocity - view.velocity;
+        let maxChange = self.profile.maxAcceleration * dt;
+        let limited = view.velocity + if change.length() > maxChange { change.normalizeOrZero() * maxChange } else { change };
+        MoveWith(mirrorVelocity(side, limited))
+    }
+}
+
+fn classicVelocity(game:&Game, profile:&AiProfile, view:&View) -> Vec2 {
+    let arrival = game.physics.paddleArrival;
+    let puckPosition = view.puckPosition;
+    let puckVelocity = view.puckVelocity;
+    let puckTarget = puckPosition + view.aimOffset;
+    let position = view.position;
+    let goal = view.ownGoal;
+    let goalDirection = (position - goal).normalizeOrZero();
+    let puckDirection = (position - puckPosition).normalizeOrZero();
+    let puckDistance = (position - puckPosition).length();
+    if // Should we dash forward (attack) ?
+           goalDirection.dot(puckDirection) < 0.
+        && puckDistance < profile.attackRadius
+        && between(puckDirection.dot(puckVelocity), -120.,600.)
+    { velocityTowards(position, puckTarget, profile.attackSpeed, arrival) }
+    else if // Should we move towards the puck (player is too far away) ?
+           goalDirection.dot(puckDirection) < 0.
+        && puckVelocity.length() < 180.
+        && distance(view.other,puckPosition) / distance(position,puckPosition) > 2.0
+    { velocityTowards(position, puckTarget, profile.defenceSpeed, arrival) }
+    else // Should we stand between puck and goal (defend) ?
+    { velocityTowards(position, (puckTarget + goal) * 0.5, profile.defenceSpeed, arrival) }
+}
+
+// Simulates the puck on its own, with the same damping and bounces off walls and obstacles as
+// the game, to find where it will cross the vertical line at x. Returns that position and the
+// time until then. The walls and obstacles are not mirrored, so this takes world coordinates.
+pub fn predictCrossing(game:&Game, position:Vec2, velocity:Vec2, x:f64, maxTime:f64) -> Option<(Vec2, f64)> {
+    let dt = TimeStep;
+    let mut puck = *game.puck;
+    puck.teleport(position);
+    puck.velocity = velocity;
+    let mut time = 0.;
+    while time < maxTime {
+        let previous = puck.position;
+        puck.update(&game.physics, dt);
+        handleObstacleCollision(game, &mut puck);
+        handleWallCollision(game, &mut puck);
+        time += dt;
+        if (previous.x - x) * (puck.position.x - x) <= 0. && previous.x != puck.position.x {
+            return Some((puck.position, time));
+        }
+        if puck.velocity.length2() < 1. {
+            return None;
+        }
+    }
+    None
+}
+
+// Whether the puck can go straight from a to b, given in the view of the side, without
+// hitting a wall or an obstacle on the way
+fn pathIsClear(game:&Game, side:Side, a:Vec2, b:Vec2) -> bool {
+    let a = mirrorPosition(game, side, a);
+    let b = mirrorPosition(game, side, b);
+    let radius = game.puck.radius;
+    // The first part of a bank shot ends touching the wall it bounces off
+    let mut clear = !game.walls.any(|wall| match wall.sweep(a, b, radius) {
+        Some((t, _)) => t < 0.999,
+        None => false
+    });
+    for game.paddles.eachi |i, &obstacle| {
+        // The first two are the player and the opponent
+        if i >= 2 && distanceToSegment(obstacle.position, a, b) < obstacle.radius + radius {
+            clear = false;
+        }
+    }
+    clear
+}
+
+// Direction to shoot the puck in from the given position to reach the goal: either straight
+// or banked off the top or bottom wall. Positions are in the view of the given side. Shots that
+// would run into a wall or an obstacle, like the corners of a rounded table, are left out. Of
+// the others the one that passes furthest from the blocker is picked.
+pub fn planShot(game:&Game, side:Side, from:Vec2, blocker:Vec2, goal:Vec2) -> Vec2 {
+    let radius = game.puck.radius;
+    let mut best = (goal - from).normalizeOrZero();
+    let mut bestClearance = -1.;
+    // Bank shots aim at the goal mirrored in the line the center of the puck bounces off;
+    // mirroring the blocker as well lets us measure the clearance along the straight,
+    // unfolded path
+    for [None, Some(radius), Some(game.field.y - radius)].each |&bounceLine| {
+        let (target, mirroredBlocker, clear) = match bounceLine {
+            None => (goal, blocker, pathIsClear(game, side, from, goal)),
+            Some(y) => {
+                let target = Vec2(goal.x, 2. * y - goal.y);
+                if from.y == target.y { loop; }
+                let bounce = from + (target - from) * ((from.y - y) / (from.y - target.y));
+                (target, Vec2(blocker.x, 2. * y - blocker.y),
+                 pathIsClear(game, side, from, bounce) && pathIsClear(game, side, bounce, goal))
+            }
+        };
+        if !clear { loop; }
+        let clearance = distanceToSegment(mirroredBlocker, from, target);
+        if clearance > bestClearance {
+            bestClearance = clearance;
+            best = (target - from).normalizeOrZero();
+        }
+    }
+    best
+}
+
+fn distanceToSegment(p:Vec2, a:Vec2, b:Vec2) -> f64 {
+    let ab = b - a;
+    let length2 = ab.length2();
+    let t = if length2 == 0. { 0. } else { f64::max(0., f64::min(1., (p - a).dot(ab) / length2)) };
+    distance(p, a + ab * t)
+}
+
+fn predictiveVelocity(game:&Game, profile:&AiProfile, view:&View) -> Vec2 {
+    let arrival = game.physics.paddleArrival;
+    let position = view.position;
+    let puckPosition = view.puckPosition;
+    let puckVelocity = view.puckVelocity;
+    let paddleRadius = game.opponent.radius;
+    let radius = paddleRadius + game.puck.radius;
+    let goal = view.ownGoal;
+    let defensiveLine = goal.x - paddleRadius * 2.5;
+    let onOurHalf = puckPosition.x > game.field.x * 0.5;
+
+    // Intercept a puck that will cross our defensive line
+    if puckVelocity.x > 0. && puckPosition.x < defensiveLine {
+        // Predict on the real table and mirror the crossing back
+        let side = view.side;
+        let line = mirrorPosition(game, side, Vec2(defensiveLine, 0.)).x;
+        match predictCrossing(game, mirrorPosition(game, side, puckPosition), mirrorVelocity(side, puckVelocity), line, 2.) {
+            Some((crossing, time)) => {
+                let target = mirrorPosition(game, side, crossing) + view.aimOffset;
+                let neededSpeed = distance(position, target) / f64::max(time, TimeStep);
+                let speed = f64::max(profile.defenceSpeed, f64::min(profile.attackSpeed, neededSpeed));
+                return velocityTowards(position, target, speed, arrival);
+            }
+            None => {}
+        }
+    }
+
+    // Shoot a slow puck on our half
+    if onOurHalf && puckVelocity.length() < 300. {
+        let shot = planShot(game, view.side, puckPosition, view.other, view.targetGoal);
+        let setup = puckPosition - shot * (radius + 10.);
+        let behindPuck = (position - puckPosition).dot(shot) < 0.;
+        return if behindPuck && distance(position, setup) < 20. {
+            // Lined up: strike through the puck
+            velocityTowards(position, puckPosition + shot * radius + view.aimOffset, profile.attackSpeed, arrival)
+        } else {
+            velocityTowards(position, setup + view.aimOffset, profile.attackSpeed, arrival)
+        };
+    }
+
+    // Stand between puck and goal (defend)
+    velocityTowards(position, (puckPosition + view.aimOffset + goal) * 0.5, profile.defenceSpeed, arrival)
+}
@@ -1,98 +1,52 @@
 extern mod sdl;
+extern mod std;
+extern mod airhockeysim;
 
 use core::num::*;
 use core::vec::*;
 use sdl::sdl::*;
 use sdl::video::*;
 use sdl::event::*;
+use sdl::joystick;
+use sdl::joystick::Joystick;
 use gl::*;
-use pendinglist::*;
 //use option::{Some, None};
-use vec2::*;
+use airhockeysim::vec2::*;
+use airhockeysim::game::*;
+use airhockeysim::rules::*;
+use airhockeysim::table::*;
+use airhockeysim::collider::*;
+use airhockeysim::replay::*;
+use airhockeysim::ai::*;
+use airhockeysim::controller::*;
+use airhockeysim::policy::*;
+use airhockeysim::options::*;
+use bindings::*;
+use std::time::precise_time_s;
 
 mod gl;
-mod vec2;
-mod pendinglist;
+mod bindings;
 
 unsafe fn glVertex(v: Vec2) {
     glVertex2f(v.x as f32, v.y as f32);
 }
 
-pub trait GameObject {
-    fn update(&mut self);
-    fn draw(&self, game: &Game);
-}
-
-struct PhysicalCircle {
-    position: Vec2,
-    velocity: Vec2,
-    radius: float
-}
-struct Paddle(PhysicalCircle);
-fn newPaddle(position:Vec2) -> @mut Paddle {
-    @mut Paddle(PhysicalCircle { position: position, velocity: Zero, radius: 40. })
-}
-
-impl GameObject for Paddle {
-    fn update(&mut self) {
-        self.position += self.velocity;
-    }
-    fn draw(&self,_:&Game) {
-        fillCircle(self.position, self.radius);
-    }
-}
-
-struct Puck(PhysicalCircle);
-fn newPuck(position:Vec2) -> @mut Puck {
-    @mut Puck(PhysicalCircle { position: position, velocity: Zero, radius: 30. })
-}
-impl GameObject for Puck {
-    fn update(&mut self) {
-        // Limit velocity of puck
-        let speed = self.velocity.length();
-        let direction = self.velocity.normalizeOrZero();
-        self.velocity = direction * if speed > 30. { 30. } else { speed };
-
-        // Apply velocity
-        self.position += self.velocity;
+// Never simulate more than this amount of time for a single rendered frame
+static MaxFrameTime: f64 = 0.25;
+// Seconds between two checks for gamepads that were plugged in or out
+static GamepadScanInterval: f64 = 2.;
 
-        // Apply damping
-        self.velocity *= 0.99;
-
-    }
-    fn draw(&self, _: &Game) {
-        fillCircle(self.position, self.radius);
-    }
-}
-
-fn newPole(position:Vec2) -> @mut Paddle {
-    @mut Paddle(PhysicalCircle { position: position, velocity: Zero, radius: 20. })
-}
-
-struct Game {
-    objects: PendingList<@GameObject>,
-    player: @mut Paddle,
-    playerScore: uint,
-    opponent: @mut Paddle,
-    opponentScore: uint,
-    puck: @mut Puck,
-    paddles: ~[@mut Paddle],
-    field: Vec2,
-    goalSize: float,
-    mouse: Vec2
-}
-
-fn circle(position:Vec2, radius:float, f:&fn(Vec2) -> bool) {
+fn circle(position:Vec2, radius:f64, f:&fn(Vec2) -> bool) {
     let vertexCount = 20;
     int::range(0, vertexCount, |i| {
-        let mut angle = (float::consts::pi * 2.0 / (vertexCount as float)) * (i as float);
-        let p = Vec2 {x: float::cos(angle), y: float::sin(angle)};
+        let mut angle = (f64::consts::pi * 2.0 / (vertexCount as f64)) * (i as f64);
+        let p = Vec2 {x: f64::cos(angle), y: f64::sin(angle)};
         let v = p * radius + position;
         f(v)
     })
 }
 
-fn strokeCircle(position:Vec2, radius:float) {
+fn strokeCircle(position:Vec2, radius:f64) {
     unsafe {
         glBegin(GL_LINE_LOOP);
         for circle(position, radius) |v| {
@@ -102,7 +56,7 @@ fn strokeCircle(position:Vec2, radius:float) {
     }
 }
 
-fn fillCircle(position: Vec2, radius: float) {
+fn fillCircle(position: Vec2, radius: f64) {
     unsafe {
         glBegin(GL_TRIANGLE_FAN);
         for circle(position, radius) |v| {
@@ -112,190 +66,259 @@ fn fillCircle(position: Vec2, radius: float) {
     }
 }
 
-fn drawGame(game: &Game) {
+fn strokeRect(min: Vec2, max: Vec2) {
+    unsafe {
+        glBegin(GL_LINE_LOOP);
+        glVertex(min);
+        glVertex(Vec2(max.x, min.y));
+        glVertex(max);
+        glVertex(Vec2(min.x, max.y));
+        glEnd();
+    }
+}
+
+fn drawPaddle(paddle: &Paddle, alpha: f64) {
+    fillCircle(paddle.interpolatedPosition(alpha), paddle.radius);
+}
+
+fn drawPuck(puck: &Puck, alpha: f64) {
+    let position = puck.interpolatedPosition(alpha);
+    fillCircle(position, puck.radius);
+
+    // Draw a marker so that spin is visible
+    let angle = puck.interpolatedAngle(alpha);
+    let marker = Vec2(f64::cos(angle), f64::sin(angle)) * (puck.radius * 0.6);
+    unsafe { glColor3f(0., 0., 0.); }
+    fillCircle(position + marker, puck.radius * 0.2);
+    unsafe { glColor3f(1., 1., 1.); }
+}
+
+// Draws the walls of the table. Those along the edges of the field fall on the edges of the window.
+fn drawWalls(game: &Game) {
+    for game.walls.each |wall| {
+        unsafe {
+            glBegin(GL_LINE_STRIP);
+            match *wall {
+                Segment(start, end) => {
+                    glVertex(start);
+                    glVertex(end);
+                }
+                Arc(center, radius, from, to) => {
+                    let start = f64::atan2(from.y, from.x);
+                    let mut sweep = f64::atan2(to.y, to.x) - start;
+                    if sweep <= 0. { sweep += f64::consts::pi * 2.0; }
+                    let vertexCount = 16;
+                    for int::range(0, vertexCount + 1) |i| {
+                        let angle = start + sweep * (i as f64) / (vertexCount as f64);
+                        glVertex(center + Vec2(f64::cos(angle), f64::sin(angle)) * radius);
+                    }
+                }
+            }
+            glEnd();
+        }
+    }
+}
+
+fn drawGame(game: &Game, inputs: &Inputs, alpha: f64, debug: bool) {
     unsafe { glClear(GL_COLOR_BUFFER_BIT); }
+    drawWalls(game);
+
+    for game.paddles.each |&paddle| {
+        drawPaddle(paddle, alpha);
+    }
+    drawPuck(game.puck, alpha);
+
+    // After a restart the centre line can not be crossed until the ring around the puck is gone
+    if game.serve.countdown > 0. {
+        unsafe {
+            glBegin(GL_LINES);
+            glVertex(Vec2(game.field.x*0.5, 0.));
+            glVertex(Vec2(game.field.x*0.5, game.field.y));
+            glEnd();
+        }
+        let fraction = game.serve.countdown / game.rules.serveCountdown;
+        strokeCircle(game.puck.interpolatedPosition(alpha), game.puck.radius + 5. + fraction * 40.);
+    }
 
-    for game.objects.each |object| {
-        object.draw(game);
+    // Show where mouse controlled paddles are heading
+    for [inputs.left, inputs.right].each |&command| {
+        match command {
+            MoveTo(target) => strokeCircle(target, 10.0),
+            MoveWith(_) => {}
+        }
     }
 
-    strokeCircle(game.mouse, 10.0);
+    if debug {
+        drawVelocities(game, alpha);
+    }
 
     drawScore(game.playerScore, Vec2(10.,10.), Vec2(10.,0.));
     drawScore(game.opponentScore, Vec2(game.field.x-10.,10.), Vec2(-10.,0.));
+    drawClock(game, Vec2(game.field.x*0.5, 25.));
+}
 
-    swap_buffers();
+// For timed matches: a pie with the time left in the period, with a dot below it for every
+// period, filled up to the current one. In overtime the pie turns into a double ring.
+fn drawClock(game: &Game, center: Vec2) {
+    let rules = &game.rules;
+    if rules.periods == 0 { return; }
+
+    let radius = 15.;
+    if game.clock.overtime {
+        strokeCircle(center, radius);
+        strokeCircle(center, radius * 0.6);
+    } else {
+        let fraction = if rules.periodLength > 0. { game.clock.remaining / rules.periodLength } else { 0. };
+        let segments = 40;
+        unsafe {
+            glBegin(GL_TRIANGLE_FAN);
+            glVertex(center);
+            for uint::range(0, ((segments as f64) * fraction) as uint + 1) |i| {
+                // Clockwise from the top
+                let angle = f64::min(i as f64 / (segments as f64), fraction) * f64::consts::pi * 2.;
+                glVertex(center + Vec2(f64::sin(angle), -f64::cos(angle)) * radius);
+            }
+            glEnd();
+        }
+        strokeCircle(center, radius);
+    }
+
+    for uint::range(0, rules.periods) |period| {
+        let position = center + Vec2(((period as f64) - (rules.periods as f64 - 1.) * 0.5) * 12., radius + 10.);
+        if period <= game.clock.period { fillCircle(position, 3.); } else { strokeCircle(position, 3.); }
+    }
+}
+
+// A line from every moving object showing where it will be a tenth of a second later
+fn drawVelocities(game: &Game, alpha: f64) {
+    let mut lines = ~[(game.puck.interpolatedPosition(alpha), game.puck.velocity)];
+    for game.paddles.each |&paddle| {
+        lines.push((paddle.interpolatedPosition(alpha), paddle.velocity));
+    }
+    unsafe {
+        glColor3f(1., 0., 0.);
+        glBegin(GL_LINES);
+        for lines.each |&(position, velocity)| {
+            glVertex(position);
+            glVertex(position + velocity * 0.1);
+        }
+        glEnd();
+        glColor3f(1., 1., 1.);
+    }
 }
 
 fn drawScore(score: uint, position: Vec2, direction: Vec2) {
-    let columns = float::sqrt(score as float) as uint;
+    let columns = f64::sqrt(score as f64) as uint;
     for core::uint::range(0,score) |index| {
         let x = index / columns;
         let y = index % columns;
         let right = direction;
         let down = Vec2(0.,1.)*direction.length();
-        fillCircle(position+right*(x as float)+down*(y as float), 5.);
+        fillCircle(position+right*(x as f64)+down*(y as f64), 5.);
     }
 }
 
-fn updateGame(game:&mut Game) {
-    for game.objects.each_mut |object| {
-        object.update();
+// Keeps track of which of the movement keys are held
+fn updateDirections(bindings: &Bindings, devices: &mut Devices, key: Key, held: bool) {
+    match bindings.action(key) {
+        Some(Move(layout, direction)) => {
+            let directions = match layout {
+                Wasd => &mut devices.wasd,
+                Arrows => &mut devices.arrows
+            };
+            match direction {
+                Up => directions.up = held,
+                Down => directions.down = held,
+                Left => directions.left = held,
+                Right => directions.right = held
+            }
+        }
+        _ => {}
     }
 }
 
-fn handleControls(game:&mut Game) {
-    let diff = game.mouse - game.player.position;
-    let dist = diff.length();
-    let maxSpeed = 50.;
-    game.player.velocity = game.player.velocity * 0.3
-        + diff.normalizeOrZero() * (if (dist < maxSpeed) { dist } else { maxSpeed }) * 0.5;
+// The open gamepads. SDL only notices gamepads that were plugged in or out when its
+// joystick subsystem is restarted, so every so often we restart it if none is in use.
+struct Gamepads {
+    joysticks: ~[~Joystick],
+    nextScan: f64
 }
 
-fn handleCollision(game:&mut Game) {
-    // Handle paddle - puck collision
-    for game.paddles.each |paddle| {
-        let diff = (game.puck.position - paddle.position);
-        if (diff.length() < game.puck.radius+paddle.radius) {
-            game.puck.velocity -= getBounceImpact(diff.normalizeOrZero(), game.puck.velocity - paddle.velocity, 0.9);
+fn Gamepads() -> Gamepads {
+    let mut gamepads = Gamepads { joysticks: ~[], nextScan: 0. };
+    gamepads.open();
+    gamepads
+}
+
+impl Gamepads {
+    fn open(&mut self) {
+        self.joysticks = ~[];
+        for int::range(0, joystick::num_joysticks()) |index| {
+            match Joystick::open(index) {
+                Ok(joystick) => self.joysticks.push(joystick),
+                Err(e) => io::println(fmt!("Could not open gamepad %d: %s", index, e))
+            }
         }
-    };
-    // Handle field boundaries - puck collision
-    match getSurface(game, game.puck) {
-        Some(surface) => {
-            game.puck.velocity -= getBounceImpact(surface, game.puck.velocity, 0.9);
+        self.nextScan = precise_time_s() as f64 + GamepadScanInterval;
+    }
+
+    fn scan(&mut self, devices: &mut Devices) {
+        if (precise_time_s() as f64) < self.nextScan { return; }
+        let count = self.joysticks.len();
+        // The old handles have to be closed while the subsystem is still running
+        self.joysticks = ~[];
+        quit_subsystem(~[InitJoystick]);
+        init_subsystem(~[InitJoystick]);
+        self.open();
+        if self.joysticks.len() != count {
+            io::println(fmt!("%u gamepads connected", self.joysticks.len()));
         }
-        None => {}
+        // Sticks of gamepads that were unplugged are released
+        devices.gamepads = ~[];
     }
 }
 
-fn between<T:Ord>(x:T, a:T, b:T) -> bool { x > a && x < b }
-fn distance(a:Vec2,b:Vec2) -> float { (a-b).length() }
-
-fn handleOpponent(game:&mut Game) {
-    let defenceSpeed = 3.;
-    let attackSpeed = 10.;
-    let position = game.opponent.position;
-    let goal = Vec2(game.field.x, game.field.y*0.5);
-    let puck = game.puck;
-    let velocity = puck.velocity;
-    let goalDirection = (position - goal).normalizeOrZero();
-    let puckDirection = (position - puck.position).normalizeOrZero();
-    let puckDistance = (position - puck.position).length();
-    let desiredVelocity = if // Should we dash forward (attack) ?
-           goalDirection.dot(puckDirection) < 0.
-        && puckDistance < 100.
-        && between(puckDirection.dot(puck.velocity), -2.,10.)
-    { velocityTowards(position, puck.position, attackSpeed) }
-    else if // Should we move towards the puck (player is too far away) ?
-           goalDirection.dot(puckDirection) < 0.
-        && velocity.length() < 3.
-        && distance(game.player.position,puck.position) / distance(position,puck.position) > 2.0
-    { velocityTowards(position, puck.position, defenceSpeed) }
-    else // Should we stand between puck and goal (defend) ?
-    { velocityTowards(position, (puck.position + goal) * 0.5, defenceSpeed) };
-
-    game.opponent.velocity = game.opponent.velocity * 0.80 + desiredVelocity * 0.15;
-}
-
-fn velocityTowards(source:Vec2, destination:Vec2, speed:float) -> Vec2 {
-    let diff = destination - source;
-    let distance = diff.length();
-    let direction = diff.normalizeOrZero();
-    if distance < speed { direction * distance }
-    else { direction * speed }
-}
-
-fn handleGoals(game:&mut Game) {
-    let p = game.puck;
-    if p.position.y > game.field.y*0.5-game.goalSize*0.5 && p.position.y < game.field.y*0.5+game.goalSize*0.5 {
-        if p.position.x < 0. && p.velocity.x < 0. {
-            p.position = game.field*0.5;
-            p.velocity = Zero;
-            game.opponentScore = game.opponentScore + 1;
-        } else if p.position.x > game.field.x && p.velocity.x > 0. {
-            p.position = game.field*0.5;
-            p.velocity = Zero;
-            game.playerScore = game.playerScore + 1;
-        }
-    }
-}
-
-fn getBounceImpact(surface:Vec2, velocity:Vec2, bounciness:float) -> Vec2 {
-    let impact = surface.dot(velocity);
-    if (impact < 0.) {
-        surface * impact * (1. + bounciness)
-    } else {
-        Vec2(0.,0.)
-    }
-}
-
-fn getSurface(game: &Game, p:&Puck) -> Option<Vec2> {
-    // Handle goals (part of the boundaries where collision is disabled)
-    if p.position.y > game.field.y*0.5-game.goalSize*0.5 && p.position.y < game.field.y*0.5+game.goalSize*0.5 { None }
-    // Handle walls
-    else if p.position.x < p.radius { Some(Vec2(1.,0.)) }
-    else if p.position.x > game.field.x-p.radius { Some(Vec2(-1.,0.)) }
-    else if (p.position.y < p.radius) { Some(Vec2(0.,1.)) }
-    else if (p.position.y > game.field.y-p.radius) { Some(Vec2(0.,-1.)) }
-    // Nothing else
-    else { None }
-}
-
-fn setupGame() -> ~Game {
-    let field = Vec2(640.,480.);
-
-    let mut player = newPaddle(Vec2(100., field.y*0.5));
-    let mut opponent = newPaddle(Vec2(field.x-100., field.y*0.5));
-    let mut puck = newPuck(Vec2{x:320.,y:240.});
-
-    let goalSize = 250.;
-
-    let mut game = ~Game {
-        objects: PendingList(),
-        field: Vec2(640.,480.),
-        goalSize: goalSize,
-        mouse: Vec2(0.,0.),
-        player: player,
-        playerScore: 0,
-        opponent: opponent,
-        opponentScore: 0,
-        puck: puck,
-        paddles: ~[
-            player,
-            opponent,
-            newPole(Vec2(0., 480.*0.5-goalSize*0.5)),
-            newPole(Vec2(0., 480.*0.5+goalSize*0.5)),
-            newPole(Vec2(640., 480.*0.5-goalSize*0.5)),
-            newPole(Vec2(640., 480.*0.5+goalSize*0.5))
-        ]
-    };
-
-    addPaddles(game);
-    game.objects.add(puck as @GameObject);
-    game.objects.handlePending();
+// What happened since the last frame
+struct Frame {
+    // Keys that were pressed
+    keys: ~[Key],
+    // The window lost the input focus
+    focusLost: bool
+}
 
-    game
+fn Frame() -> Frame {
+    Frame { keys: ~[], focusLost: false }
 }
 
-fn addPaddles(game:&mut Game) {
-    for game.paddles.each |&paddle| {
-        game.objects.add(paddle as @GameObject);
+pub impl Frame {
+    fn isPressed(&self, bindings: &Bindings, action: Action) -> bool {
+        self.keys.any(|&key| bindings.isBound(key, action))
     }
 }
 
-fn handleSDLEvents(game: &mut Game) -> bool {
+// Updates the devices and collects what happened in `frame`, returns false when the window is closed
+fn handleSDLEvents(bindings: &Bindings, devices: &mut Devices, frame: &mut Frame) -> bool {
     loop {
         let event = poll_event();
         match event {
-            KeyEvent(keycode,state,wrap_mod_state,unicode) => {
-                if (keycode == EscapeKey) {
-                    return false;
+            KeyEvent(keycode,state,_,_) => {
+                if (state) {
+                    frame.keys.push(keycode);
                 }
-                io::println(fmt!("%? %? %? %?", keycode, state, wrap_mod_state, unicode));
+                updateDirections(bindings, devices, keycode, state);
             }
             MouseMotionEvent(_,x,y,_,_) => {
-                game.mouse = Vec2(x as float, y as float);
+                devices.mouse = Vec2(x as f64, y as f64);
+            }
+            JoyAxisEvent(gamepad,axis,value) => {
+                setGamepadAxis(devices, gamepad as uint, axis as uint, value);
+            }
+            // Only losing the keyboard or being minimized counts, the mouse leaving
+            // the window does not
+            ActiveEvent(false, states) => {
+                if states.any(|&state| match state { AppInputFocus | AppActive => true, AppMouseFocus => false }) {
+                    frame.focusLost = true;
+                }
             }
             QuitEvent => {
                 return false;
@@ -307,8 +330,874 @@ fn handleSDLEvents(game: &mut Game) -> bool {
     return true;
 }
 
-fn gameLoop(game: &mut Game, update: &fn(&mut Game) -> bool) {
-    while handleSDLEvents(game) && update(game) {
+// Runs `update` once for every frame, until it returns false or the window is closed.
+// While hotplug is set, gamepads that are plugged in or out are noticed. That restarts the
+// joystick subsystem, which can hold up a frame and forgets where the sticks are, so `update`
+// can switch it off during play.
+fn eventLoop(bindings: &Bindings, devices: &mut Devices, hotplug: @mut bool, update: &fn(&Devices, &Frame) -> bool) {
+    let mut frame = Frame();
+    let mut gamepads = Gamepads();
+    while handleSDLEvents(bindings, devices, &mut frame) && update(devices, &frame) {
+        frame = Frame();
+        if *hotplug {
+            gamepads.scan(devices);
+        }
+    }
+}
+
+// Measures the time between rendered frames
+struct FrameClock {
+    previousTime: f64
+}
+fn FrameClock() -> FrameClock {
+    FrameClock { previousTime: precise_time_s() as f64 }
+}
+impl FrameClock {
+    fn tick(&mut self) -> f64 {
+        let now = precise_time_s() as f64;
+        let frameTime = now - self.previousTime;
+        self.previousTime = now;
+        if frameTime > MaxFrameTime { MaxFrameTime } else { frameTime }
+    }
+}
+
+// Middle of the half of the field a side plays on
+fn halfCenter(field: Vec2, side: Side) -> Vec2 {
+    match side {
+        LeftSide => Vec2(field.x * 0.25, field.y * 0.5),
+        RightSide => Vec2(field.x * 0.75, field.y * 0.5)
+    }
+}
+
+// One row per level, the stronger the opponent the more dots
+fn drawAiMenu(field: Vec2, levels: uint, selected: uint) {
+    unsafe { glClear(GL_COLOR_BUFFER_BIT); }
+
+    for uint::range(0, levels) |row| {
+        let center = field * 0.5 + Vec2(0., ((row as f64) - (levels as f64 - 1.) * 0.5) * 70.);
+        for uint::range(0, row + 1) |dot| {
+            fillCircle(center + Vec2(((dot as f64) - (row as f64) * 0.5) * 30., 0.), 10.);
+        }
+        if row == selected {
+            strokeRect(center - Vec2(100., 25.), center + Vec2(100., 25.));
+        }
+    }
+
+    swap_buffers();
+}
+
+// Lets the player pick the strength of the opponent with the up and down keys of either
+// layout and confirm. Returns None when the game is closed instead.
+fn chooseAiLevel(bindings: &Bindings, field: Vec2) -> Option<AiLevel> {
+    let levels = allAiLevels();
+    let mut selected = 1u;
+    let mut chosen = None;
+    let mut devices = Devices();
+
+    for eventLoop(bindings, &mut devices, @mut true) |_, frame| {
+        if frame.isPressed(bindings, Quit) { break; }
+        for frame.keys.each |&key| {
+            match bindings.action(key) {
+                Some(Move(_, Up)) if selected > 0 => { selected -= 1; }
+                Some(Move(_, Down)) if selected + 1 < levels.len() => { selected += 1; }
+                Some(Confirm) => { chosen = Some(levels[selected]); }
+                _ => {}
+            }
+        }
+        if chosen.is_some() { break; }
+        drawAiMenu(field, levels.len(), selected);
+    };
+    chosen
+}
+
+fn triangle(a: Vec2, b: Vec2, c: Vec2) {
+    unsafe {
+        glBegin(GL_TRIANGLES);
+        glVertex(a);
+        glVertex(b);
+        glVertex(c);
+        glEnd();
+    }
+}
+
+// A small picture of each kind of controller, as there is no text
+fn drawControllerIcon(name: &str, center: Vec2) {
+    match name {
+        "mouse" => {
+            strokeRect(center - Vec2(12., 18.), center + Vec2(12., 18.));
+            strokeRect(center - Vec2(12., 18.), center + Vec2(0., -4.));
+        }
+        "wasd" => {
+            // The keys as an inverted T, on the left of the keyboard
+            let key = Vec2(8., 8.);
+            for [Vec2(0., -10.), Vec2(-20., 10.), Vec2(0., 10.), Vec2(20., 10.)].each |&offset| {
+                strokeRect(center + offset - key, center + offset + key);
+            }
+            strokeRect(center - Vec2(30., 20.), center + Vec2(30., 20.));
+        }
+        "arrows" => {
+            triangle(center + Vec2(0., -20.), center + Vec2(-8., -8.), center + Vec2(8., -8.));
+            triangle(center + Vec2(0., 20.), center + Vec2(-8., 8.), center + Vec2(8., 8.));
+            triangle(center + Vec2(-20., 0.), center + Vec2(-8., -8.), center + Vec2(-8., 8.));
+            triangle(center + Vec2(20., 0.), center + Vec2(8., -8.), center + Vec2(8., 8.));
+        }
+        "gamepad" | "gamepad2" => {
+            strokeRect(center - Vec2(30., 15.), center + Vec2(30., 15.));
+            // One button for the first gamepad, two for the second
+            let buttons = if name == "gamepad" { 1 } else { 2 };
+            for uint::range(0, buttons) |i| {
+                fillCircle(center + Vec2(12. + (i as f64) * 10., 0.), 4.);
+            }
+            strokeCircle(center - Vec2(15., 0.), 7.);
+        }
+        "ai" => {
+            fillCircle(center, 12.);
+            strokeCircle(center, 20.);
+        }
+        _ => {
+            // Policy networks: a few connected neurons
+            let inputs = [center + Vec2(-20., -12.), center + Vec2(-20., 12.)];
+            let output = center + Vec2(20., 0.);
+            unsafe {
+                glBegin(GL_LINES);
+                for inputs.each |&input| {
+                    glVertex(input);
+                    glVertex(output);
+                }
+                glEnd();
+            }
+            for inputs.each |&input| {
+                fillCircle(input, 6.);
+            }
+            fillCircle(output, 6.);
+        }
+    }
+}
+
+// A column of controllers for each side of the table
+fn drawSetup(field: Vec2, names: &[~str], left: uint, right: uint) {
+    unsafe { glClear(GL_COLOR_BUFFER_BIT); }
+
+    for names.eachi |row, name| {
+        let offset = Vec2(0., ((row as f64) - (names.len() as f64 - 1.) * 0.5) * 60.);
+        for [(LeftSide, left), (RightSide, right)].each |&(side, selected)| {
+            let center = halfCenter(field, side) + offset;
+            drawControllerIcon(*name, center);
+            if row == selected {
+                strokeRect(center - Vec2(80., 27.), center + Vec2(80., 27.));
+            }
+        }
+    }
+    // The line in the middle of the table
+    unsafe {
+        glBegin(GL_LINES);
+        glVertex(Vec2(field.x * 0.5, 20.));
+        glVertex(Vec2(field.x * 0.5, field.y - 20.));
+        glEnd();
+    }
+
+    swap_buffers();
+}
+
+// Lets the players pick what controls each side: the up and down keys of the wasd layout choose
+// for the left side, those of the arrows layout for the right side and confirm starts.
+// Returns None when the game is closed instead.
+fn chooseControllers(bindings: &Bindings, field: Vec2, names: &[~str], left: uint, right: uint) -> Option<(~str, ~str)> {
+    let mut left = left;
+    let mut right = right;
+    let mut chosen = None;
+    let mut devices = Devices();
+
+    for eventLoop(bindings, &mut devices, @mut true) |_, frame| {
+        if frame.isPressed(bindings, Quit) { break; }
+        for frame.keys.each |&key| {
+            match bindings.action(key) {
+                Some(Move(Wasd, Up)) if left > 0 => { left -= 1; }
+                Some(Move(Wasd, Down)) if left + 1 < names.len() => { left += 1; }
+                Some(Move(Arrows, Up)) if right > 0 => { right -= 1; }
+                Some(Move(Arrows, Down)) if right + 1 < names.len() => { right += 1; }
+                Some(Confirm) => { chosen = Some((copy names[left], copy names[right])); }
+                _ => {}
+            }
+        }
+        if chosen.is_some() { break; }
+        drawSetup(field, names, left, right);
+    };
+    chosen
+}
+
+fn fillRect(min: Vec2, max: Vec2) {
+    unsafe {
+        glBegin(GL_QUADS);
+        glVertex(min);
+        glVertex(Vec2(max.x, min.y));
+        glVertex(max);
+        glVertex(Vec2(min.x, max.y));
+        glEnd();
+    }
+}
+
+// A black box to draw a prompt on top of the game
+fn drawPanel(center: Vec2, size: Vec2) {
+    unsafe { glColor3f(0., 0., 0.); }
+    fillRect(center - size * 0.5, center + size * 0.5);
+    unsafe { glColor3f(1., 1., 1.); }
+    strokeRect(center - size * 0.5, center + size * 0.5);
+}
+
+fn drawPlayIcon(center: Vec2) {
+    triangle(center + Vec2(-12., -15.), center + Vec2(-12., 15.), center + Vec2(15., 0.));
+}
+
+fn drawPauseIcon(center: Vec2) {
+    fillRect(center + Vec2(-15., -20.), center + Vec2(-5., 20.));
+    fillRect(center + Vec2(5., -20.), center + Vec2(15., 20.));
+}
+
+fn drawRestartIcon(center: Vec2) {
+    strokeCircle(center, 15.);
+    triangle(center + Vec2(15., -8.), center + Vec2(8., 3.), center + Vec2(22., 3.));
+}
+
+fn drawQuitIcon(center: Vec2) {
+    unsafe {
+        glBegin(GL_LINES);
+        glVertex(center + Vec2(-15., -15.));
+        glVertex(center + Vec2(15., 15.));
+        glVertex(center + Vec2(15., -15.));
+        glVertex(center + Vec2(-15., 15.));
+        glEnd();
+    }
+}
+
+enum Icon { PlayIcon, RestartIcon, QuitIcon }
+
+fn drawIcon(icon: Icon, center: Vec2) {
+    match icon {
+        PlayIcon => drawPlayIcon(center),
+        RestartIcon => drawRestartIcon(center),
+        QuitIcon => drawQuitIcon(center)
+    }
+}
+
+// A row of icons, with a box around the selected one
+fn drawChoices(icons: &[Icon], selected: uint, center: Vec2) {
+    for icons.eachi |i, &icon| {
+        let position = center + Vec2(((i as f64) - (icons.len() as f64 - 1.) * 0.5) * 80., 0.);
+        drawIcon(icon, position);
+        if i == selected {
+            strokeRect(position - Vec2(30., 30.), position + Vec2(30., 30.));
+        }
+    }
+}
+
+// Moves a selection left and right through `count` choices, with the keys of either layout
+fn selectChoice(selected: uint, count: uint, action: Option<Action>) -> uint {
+    match action {
+        Some(Move(_, Left)) if selected > 0 => selected - 1,
+        Some(Move(_, Right)) if selected + 1 < count => selected + 1,
+        _ => selected
+    }
+}
+
+// Seconds the game stands still after a goal
+static GoalPause: f64 = 1.5;
+
+// Everything about the match that outlives a single state
+struct Session {
+    game: ~Game,
+    left: ~Controller,
+    right: ~Controller,
+    replay: Replay,
+    // Inputs of the last step
+    inputs: Inputs,
+    halfFieldRule: bool,
+    rules: MatchRules,
+    table: Table,
+    // A gamepad controls one of the paddles
+    usesGamepad: bool,
+    // Where to save the recordings, and how many matches were saved so far
+    recordPath: Option<~str>,
+    recorded: uint,
+    debug: bool
+}
+
+fn Session(seed: u64, halfFieldRule: bool, rules: MatchRules, table: Table, left: ~Controller, right: ~Controller) -> Session {
+    let mut game = setupGameOnTable(seed, &table);
+    game.halfFieldRule = halfFieldRule;
+    setRules(game, rules);
+    let inputs = Inputs { left: MoveTo(game.player.position), right: MoveTo(game.opponent.position) };
+    Session {
+        game: game,
+        left: left,
+        right: right,
+        replay: Replay(seed, halfFieldRule, rules, copy table),
+        inputs: inputs,
+        halfFieldRule: halfFieldRule,
+        rules: rules,
+        table: table,
+        usesGamepad: false,
+        recordPath: None,
+        recorded: 0,
+        debug: false
+    }
+}
+
+pub impl Session {
+    // Starts a new match with the same controllers. The recording of the old match is
+    // saved first, the new match starts a new one.
+    fn restart(&mut self) {
+        self.saveReplay();
+        let seed = (precise_time_s() as f64 * 1000000.) as u64;
+        self.game = setupGameOnTable(seed, &self.table);
+        self.game.halfFieldRule = self.halfFieldRule;
+        setRules(self.game, self.rules);
+        self.replay = Replay(seed, self.halfFieldRule, self.rules, copy self.table);
+    }
+
+    // Writes the recording of the current match. The first match goes to the record path,
+    // later ones get their number appended: match.replay, match-2.replay, match-3.replay...
+    fn saveReplay(&mut self) {
+        let path = match self.recordPath { Some(copy path) => Path(path), None => return };
+        if self.replay.len() == 0 { return; }
+        self.recorded += 1;
+        let path = if self.recorded == 1 { path } else {
+            let stem = path.filestem().get_or_default(~"replay");
+            path.with_filestem(fmt!("%s-%u", stem, self.recorded))
+        };
+        match writeReplay(&path, &self.replay) {
+            Ok(()) => {}
+            Err(e) => io::println(fmt!("Could not write replay %s: %s", path.to_str(), e))
+        }
+    }
+
+    fn draw(&self, alpha: f64) {
+        drawGame(self.game, &self.inputs, alpha, self.debug);
+    }
+}
+
+// What the active state wants to happen next
+enum Transition {
+    Stay,
+    Switch(~State),
+    Exit
+}
+
+// A screen of the game. Each state handles its own input, update and draw,
+// gameLoop passes everything on to the active one.
+trait State {
+    fn handleKey(&mut self, session: &mut Session, key: Key, action: Option<Action>) -> Transition;
+    fn focusLost(&mut self, session: &mut Session) -> Transition;
+    fn update(&mut self, session: &mut Session, devices: &Devices, frameTime: f64) -> Transition;
+    fn draw(&self, session: &Session);
+    // Whether gamepads that are plugged in or out are looked for. Not during play, as
+    // that can hold up a frame.
+    fn scansGamepads(&self) -> bool;
+}
+
+// Waits for the players to get ready
+struct TitleState;
+
+impl State for TitleState {
+    fn handleKey(&mut self, _: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Switch(~QuitPrompt(~TitleState as ~State) as ~State),
+            Some(Confirm) => Switch(~PlayingState() as ~State),
+            _ => Stay
+        }
+    }
+    // Play only starts with a key press, which needs the focus back anyway
+    fn focusLost(&mut self, _: &mut Session) -> Transition { Stay }
+    fn scansGamepads(&self) -> bool { true }
+    fn update(&mut self, _: &mut Session, _: &Devices, _: f64) -> Transition { Stay }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        let center = session.game.field * 0.5;
+        drawPanel(center, Vec2(120., 80.));
+        drawPlayIcon(center);
+    }
+}
+
+struct PlayingState {
+    // Simulation time that still has to be processed
+    accumulator: f64
+}
+
+fn PlayingState() -> PlayingState {
+    PlayingState { accumulator: 0. }
+}
+
+impl State for PlayingState {
+    fn handleKey(&mut self, session: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Switch(~MenuState() as ~State),
+            Some(Pause) => Switch(~PausedState as ~State),
+            Some(Restart) => { session.restart(); Stay }
+            Some(ServeReset) => {
+                resetServe(session.game);
+                session.replay.recordServeReset();
+                Stay
+            }
+            Some(ToggleDebug) => { session.debug = !session.debug; Stay }
+            _ => Stay
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition {
+        Switch(~PausedState as ~State)
+    }
+    fn scansGamepads(&self) -> bool { false }
+    fn update(&mut self, session: &mut Session, devices: &Devices, frameTime: f64) -> Transition {
+        self.accumulator += frameTime;
+        while self.accumulator >= TimeStep {
+            session.inputs = stepControllers(session.game, &mut session.left, &mut session.right, devices);
+            session.replay.record(session.inputs);
+            self.accumulator -= TimeStep;
+
+            for session.game.events.each |&event| {
+                match event {
+                    GoalScored(side) => return Switch(~GoalScoredState(side) as ~State),
+                    // The time ran out
+                    MatchEnded(winner) => return Switch(~MatchOverState { winner: winner } as ~State),
+                    PeriodEnded | Foul(_) => {}
+                }
+            }
+        }
+        Stay
+    }
+    fn draw(&self, session: &Session) {
+        // Render somewhere between the last two simulation states
+        session.draw(self.accumulator / TimeStep);
+    }
+}
+
+struct PausedState;
+
+impl State for PausedState {
+    fn handleKey(&mut self, _: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Pause) => Switch(~PlayingState() as ~State),
+            Some(Quit) => Switch(~MenuState() as ~State),
+            _ => Stay
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition { Stay }
+    fn scansGamepads(&self) -> bool { true }
+    fn update(&mut self, _: &mut Session, _: &Devices, _: f64) -> Transition { Stay }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        let center = session.game.field * 0.5;
+        drawPanel(center, Vec2(120., 80.));
+        drawPauseIcon(center);
+    }
+}
+
+// The menu during a match: resume, restart or quit
+struct MenuState {
+    selected: uint
+}
+
+fn MenuState() -> MenuState {
+    MenuState { selected: 0 }
+}
+
+impl State for MenuState {
+    fn handleKey(&mut self, session: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Switch(~PlayingState() as ~State),
+            Some(Confirm) => match self.selected {
+                0 => Switch(~PlayingState() as ~State),
+                1 => { session.restart(); Switch(~TitleState as ~State) }
+                _ => Switch(~QuitPrompt(~MenuState() as ~State) as ~State)
+            },
+            _ => { self.selected = selectChoice(self.selected, 3, action); Stay }
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition { Stay }
+    fn scansGamepads(&self) -> bool { true }
+    fn update(&mut self, _: &mut Session, _: &Devices, _: f64) -> Transition { Stay }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        let center = session.game.field * 0.5;
+        drawPanel(center, Vec2(280., 100.));
+        drawChoices([PlayIcon, RestartIcon, QuitIcon], self.selected, center);
+    }
+}
+
+// Really quit? Quit again or choose the cross to leave, anything else goes back
+struct QuitPrompt {
+    back: Option<~State>,
+    selected: uint
+}
+
+fn QuitPrompt(back: ~State) -> QuitPrompt {
+    QuitPrompt { back: Some(back), selected: 1 }
+}
+
+pub impl QuitPrompt {
+    fn goBack(&mut self) -> Transition {
+        Switch(self.back.swap_unwrap())
+    }
+}
+
+impl State for QuitPrompt {
+    fn handleKey(&mut self, _: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Exit,
+            Some(Confirm) => if self.selected == 0 { Exit } else { self.goBack() },
+            Some(Move(_, Left)) | Some(Move(_, Right)) => { self.selected = selectChoice(self.selected, 2, action); Stay }
+            _ => self.goBack()
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition { Stay }
+    fn scansGamepads(&self) -> bool { true }
+    fn update(&mut self, _: &mut Session, _: &Devices, _: f64) -> Transition { Stay }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        let center = session.game.field * 0.5;
+        drawPanel(center, Vec2(200., 100.));
+        drawChoices([QuitIcon, PlayIcon], self.selected, center);
+    }
+}
+
+// The game stands still for a moment after a goal
+struct GoalScoredState {
+    side: Side,
+    remaining: f64,
+    // The window lost the focus during the pause, so play does not go on afterwards
+    unfocused: bool
+}
+
+fn GoalScoredState(side: Side) -> GoalScoredState {
+    GoalScoredState { side: side, remaining: GoalPause, unfocused: false }
+}
+
+impl State for GoalScoredState {
+    fn handleKey(&mut self, _: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Switch(~MenuState() as ~State),
+            _ => Stay
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition {
+        self.unfocused = true;
+        Stay
+    }
+    fn scansGamepads(&self) -> bool { false }
+    fn update(&mut self, session: &mut Session, _: &Devices, frameTime: f64) -> Transition {
+        self.remaining -= frameTime;
+        if self.remaining > 0. { return Stay; }
+        if session.game.clock.over {
+            Switch(~MatchOverState { winner: session.game.clock.winner } as ~State)
+        } else if self.unfocused {
+            Switch(~PausedState as ~State)
+        } else {
+            Switch(~PlayingState() as ~State)
+        }
+    }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        // Rings spreading from the goal the puck went into
+        let goal = session.game.goal(opposite(self.side)).center;
+        let progress = 1. - self.remaining / GoalPause;
+        for uint::range(1, 4) |ring| {
+            strokeCircle(goal, (ring as f64) * 40. + progress * 80.);
+        }
+    }
+}
+
+struct MatchOverState {
+    // None for a draw
+    winner: Option<Side>
+}
+
+impl State for MatchOverState {
+    fn handleKey(&mut self, session: &mut Session, _: Key, action: Option<Action>) -> Transition {
+        match action {
+            Some(Quit) => Switch(~QuitPrompt(~MatchOverState { winner: self.winner } as ~State) as ~State),
+            Some(Confirm) | Some(Restart) => { session.restart(); Switch(~TitleState as ~State) }
+            _ => Stay
+        }
+    }
+    fn focusLost(&mut self, _: &mut Session) -> Transition { Stay }
+    fn scansGamepads(&self) -> bool { true }
+    fn update(&mut self, _: &mut Session, _: &Devices, _: f64) -> Transition { Stay }
+    fn draw(&self, session: &Session) {
+        session.draw(1.);
+        // A big disc on the half of the winner, or a small one on both halves for a draw
+        let field = session.game.field;
+        match self.winner {
+            Some(side) => {
+                drawPanel(halfCenter(field, side), Vec2(160., 160.));
+                fillCircle(halfCenter(field, side), 50.);
+            }
+            None => {
+                for [LeftSide, RightSide].each |&side| {
+                    drawPanel(halfCenter(field, side), Vec2(100., 100.));
+                    fillCircle(halfCenter(field, side), 25.);
+                }
+            }
+        }
+    }
+}
+
+// Makes the transition, returns false when the game should exit
+fn switchState(state: &mut ~State, transition: Transition) -> bool {
+    match transition {
+        Stay => true,
+        Switch(next) => { *state = next; true }
+        Exit => false
+    }
+}
+
+fn gameLoop(bindings: &Bindings, session: &mut Session, state: ~State) {
+    let mut state = state;
+    let mut devices = Devices();
+    devices.mouse = session.game.player.position;
+    let mut clock = FrameClock();
+    let hotplug = @mut false;
+
+    for eventLoop(bindings, &mut devices, hotplug) |devices, frame| {
+        let mut running = true;
+        for frame.keys.each |&key| {
+            if running {
+                let transition = state.handleKey(session, key, bindings.action(key));
+                running = switchState(&mut state, transition);
+            }
+        }
+        if running && frame.focusLost {
+            let transition = state.focusLost(session);
+            running = switchState(&mut state, transition);
+        }
+        let frameTime = clock.tick();
+        if running {
+            let transition = state.update(session, devices, frameTime);
+            running = switchState(&mut state, transition);
+        }
+        if running {
+            state.draw(session);
+            swap_buffers();
+            *hotplug = state.scansGamepads() && !session.usesGamepad;
+        }
+        running
+    };
+}
+
+fn playGame(bindings: &Bindings, seed: u64, halfFieldRule: bool, rules: MatchRules, table: Table,
+            left: ~Controller, right: ~Controller, usesGamepad: bool, recordPath: Option<~str>) {
+    let mut session = Session(seed, halfFieldRule, rules, table, left, right);
+    session.usesGamepad = usesGamepad;
+    session.recordPath = recordPath;
+    gameLoop(bindings, &mut session, ~TitleState as ~State);
+    session.saveReplay();
+}
+
+// Plays back a replay. The pause or confirm key pauses. The directions of either keyboard
+// layout control the playback: right steps a single step while paused, up and down change
+// the playback speed and left rewinds a second.
+fn playReplay(bindings: &Bindings, replay: &Replay) {
+    let mut game = replay.newGame();
+    // Number of steps that have been simulated
+    let mut position = 0u;
+    let mut paused = false;
+    let mut debug = false;
+    let mut speed = 1.;
+    let mut accumulator = 0.;
+    let mut clock = FrameClock();
+    // The devices are ignored during playback
+    let mut devices = Devices();
+
+    for eventLoop(bindings, &mut devices, @mut false) |_, frame| {
+        if frame.isPressed(bindings, Quit) { break; }
+        let frameTime = clock.tick();
+        for frame.keys.each |&key| {
+            match bindings.action(key) {
+                Some(Pause) | Some(Confirm) => { paused = !paused; }
+                Some(ToggleDebug) => { debug = !debug; }
+                Some(Move(_, Right)) if paused && position < replay.len() => {
+                    replay.stepGame(game, position);
+                    position += 1;
+                }
+                Some(Move(_, Up)) => { speed = f64::min(speed * 2., 16.); }
+                Some(Move(_, Down)) => { speed = f64::max(speed * 0.5, 0.25); }
+                Some(Move(_, Left)) => {
+                    // The simulation is deterministic, so simulating from the start gets us back exactly
+                    let stepsPerSecond = (1. / TimeStep) as uint;
+                    position = if position > stepsPerSecond { position - stepsPerSecond } else { 0 };
+                    game = replay.gameAt(position);
+                    accumulator = 0.;
+                }
+                _ => {}
+            }
+        }
+
+        if !paused {
+            accumulator += frameTime * speed;
+            while accumulator >= TimeStep && position < replay.len() {
+                replay.stepGame(game, position);
+                position += 1;
+                accumulator -= TimeStep;
+            }
+        }
+
+        let alpha = if paused || position == replay.len() { 1. } else { accumulator / TimeStep };
+        let recorded = if position > 0 { replay.inputs[position - 1] } else { Inputs { left: MoveWith(Zero), right: MoveWith(Zero) } };
+        drawGame(game, &recorded, alpha, debug);
+        swap_buffers();
+    };
+}
+
+// Match rules from --first-to, --periods, --period-length, --serve-countdown,
+// --stuck-time, --win-by-two and --no-overtime
+fn matchRules(args: &[~str]) -> Option<MatchRules> {
+    let mut rules = defaultMatchRules();
+    match getOption(args, "--first-to") {
+        Some(value) => match uint::from_str(value) {
+            Some(goals) => rules.goalsToWin = goals,
+            None => { io::println(fmt!("Invalid number of goals %s", value)); return None; }
+        },
+        None => {}
+    }
+    match getOption(args, "--periods") {
+        Some(value) => match uint::from_str(value) {
+            Some(periods) => { rules.periods = periods; rules.periodLength = 120.; }
+            None => { io::println(fmt!("Invalid number of periods %s", value)); return None; }
+        },
+        None => {}
+    }
+    match getOption(args, "--period-length") {
+        Some(value) => match f64::from_str(value) {
+            Some(seconds) if seconds > 0. => rules.periodLength = seconds,
+            _ => { io::println(fmt!("Invalid period length %s", value)); return None; }
+        },
+        None => {}
+    }
+    match getOption(args, "--serve-countdown") {
+        Some(value) => match f64::from_str(value) {
+            Some(seconds) if seconds >= 0. => rules.serveCountdown = seconds,
+            _ => { io::println(fmt!("Invalid serve countdown %s", value)); return None; }
+        },
+        None => {}
+    }
+    match getOption(args, "--stuck-time") {
+        Some(value) => match f64::from_str(value) {
+            Some(seconds) if seconds >= 0. => rules.stuckTime = seconds,
+            _ => { io::println(fmt!("Invalid stuck time %s", value)); return None; }
+        },
+        None => {}
+    }
+    rules.winByTwo = vec::contains(args, &~"--win-by-two");
+    rules.overtime = !vec::contains(args, &~"--no-overtime");
+    Some(rules)
+}
+
+// Key bindings from the file given with --bindings, or else from ~/.airhockey-bindings
+// when it exists. Returns None when the file has problems, after reporting them.
+fn loadBindings(args: &[~str]) -> Option<Bindings> {
+    let path = match getOption(args, "--bindings") {
+        Some(path) => Path(path),
+        None => match os::homedir() {
+            Some(home) if os::path_exists(&home.push(".airhockey-bindings")) => home.push(".airhockey-bindings"),
+            _ => return Some(defaultBindings())
+        }
+    };
+    match readBindings(&path) {
+        Ok(bindings) => Some(bindings),
+        Err(errors) => {
+            io::println(fmt!("Could not use bindings %s:", path.to_str()));
+            for errors.each |error| {
+                io::println(fmt!("  %s", *error));
+            }
+            None
+        }
+    }
+}
+
+// The table from the file given with --table, or else the default one.
+// Returns None when the file has problems, after reporting them.
+fn loadTable(args: &[~str]) -> Option<Table> {
+    let path = match getOption(args, "--table") {
+        Some(path) => Path(path),
+        None => return Some(defaultTable())
+    };
+    match readTable(&path) {
+        Ok(table) => Some(table),
+        Err(errors) => {
+            io::println(fmt!("Could not use table %s:", path.to_str()));
+            for errors.each |error| {
+                io::println(fmt!("  %s", *error));
+            }
+            None
+        }
+    }
+}
+
+// Gamepad settings from --stick-mode, --deadzone and --stick-curve
+fn stickSettings(args: &[~str]) -> Option<StickSettings> {
+    let mut settings = defaultStickSettings();
+    match getOption(args, "--stick-mode") {
+        Some(~"velocity") => settings.mode = StickVelocity,
+        Some(~"offset") => settings.mode = StickOffset,
+        Some(mode) => { io::println(fmt!("Unknown stick mode %s, expected velocity or offset", mode)); return None; }
+        None => {}
+    }
+    match getOption(args, "--deadzone") {
+        Some(value) => match f64::from_str(value) {
+            Some(deadzone) if deadzone >= 0. && deadzone < 1. => settings.deadzone = deadzone,
+            _ => { io::println(fmt!("Invalid deadzone %s, expected a value from 0 to 1", value)); return None; }
+        },
+        None => {}
+    }
+    match getOption(args, "--stick-curve") {
+        Some(value) => match f64::from_str(value) {
+            Some(curve) if curve > 0. => settings.curve = curve,
+            _ => { io::println(fmt!("Invalid stick curve %s", value)); return None; }
+        },
+        None => {}
+    }
+    Some(settings)
+}
+
+// Creates the controller for one side. A policy network is loaded from the file given
+// with --policy and decides --policy-rate times per second, 30 by default.
+fn makeController(args: &[~str], name: &str, aiLevel: AiLevel, aiStyle: AiStyle, seed: u64) -> Option<~Controller> {
+    if name == "gamepad" || name == "gamepad2" {
+        let index = if name == "gamepad" { 0 } else { 1 };
+        return stickSettings(args).map(|&settings| ~GamepadController(index, settings) as ~Controller);
+    }
+    if name != "policy" {
+        let controller = controllerFromName(name, aiLevel, aiStyle, seed);
+        if controller.is_none() {
+            io::println(fmt!("Unknown controller %s, expected mouse, wasd, arrows, gamepad, gamepad2, ai or policy", name));
+        }
+        return controller;
+    }
+
+    let rate = match getOption(args, "--policy-rate") {
+        Some(value) => match f64::from_str(value) {
+            Some(rate) if rate > 0. => rate,
+            _ => { io::println(fmt!("Invalid policy rate %s", value)); return None; }
+        },
+        None => 30.
+    };
+    match getOption(args, "--policy") {
+        Some(path) => match readNetwork(&Path(path)) {
+            Ok(network) => Some(~PolicyController(network, rate) as ~Controller),
+            Err(e) => { io::println(fmt!("Could not read policy %s: %s", path, e)); None }
+        },
+        None => { io::println("The policy controller needs --policy <file>"); None }
+    }
+}
+
+// Opens a window that shows the whole field, one pixel for each unit
+fn setupVideo(field: Vec2) {
+    set_video_mode(field.x as int, field.y as int, 32, &[], &[DoubleBuf,OpenGL]);
+
+    unsafe {
+        // Initialize graphics
+        glMatrixMode(GL_PROJECTION);
+        glOrtho(0.0, field.x, field.y, 0.0, 0.0, 1.0);
+        glMatrixMode(GL_MODELVIEW);
+        glLoadIdentity();
     }
 }
 
@@ -316,27 +1205,83 @@ fn main() {
     do sdl::start {
         let init_flags = ~[InitEverything];
         init(init_flags);
-        set_video_mode(640,480,32,&[],&[DoubleBuf,OpenGL]);
 
-        unsafe {
-            // Initialize graphics
-            glMatrixMode(GL_PROJECTION);
-            glOrtho(0.0,640.0,480.0,0.0,0.0,1.0);
-            glMatrixMode(GL_MODELVIEW);
-            glLoadIdentity();
-        }
-    
-        let mut game = setupGame();
-
-        for gameLoop(game) |game|{
-            handleControls(game);
-            updateGame(game);
-            handleOpponent(game);
-            handleCollision(game);
-            handleGoals(game);
-            drawGame(game);
-            game.objects.handlePending();
+        let args = os::args();
+        let bindings = match loadBindings(args) {
+            Some(bindings) => bindings,
+            None => { quit(); return; }
         };
+        let rules = match matchRules(args) {
+            Some(rules) => rules,
+            None => { quit(); return; }
+        };
+        match getOption(args, "--replay") {
+            Some(path) => match readReplay(&Path(path)) {
+                Ok(replay) => {
+                    setupVideo(replay.table.field);
+                    playReplay(&bindings, &replay);
+                }
+                Err(e) => io::println(fmt!("Could not read replay %s: %s", path, e))
+            },
+            None => {
+                let table = match loadTable(args) {
+                    Some(table) => table,
+                    None => { quit(); return; }
+                };
+                setupVideo(table.field);
+                // Without any controller options the players choose on the setup screen
+                let controllers = match (getOption(args, "--left"), getOption(args, "--right")) {
+                    (None, None) => {
+                        let mut names = ~[~"mouse", ~"wasd", ~"arrows", ~"gamepad", ~"gamepad2", ~"ai"];
+                        if getOption(args, "--policy").is_some() {
+                            names.push(~"policy");
+                        }
+                        chooseControllers(&bindings, table.field, names, 0, 5)
+                    }
+                    (left, right) => Some((left.get_or_default(~"mouse"), right.get_or_default(~"ai")))
+                };
+                let (left, right) = match controllers {
+                    Some(controllers) => controllers,
+                    None => { quit(); return; }
+                };
+                let usesAi = left == ~"ai" || right == ~"ai";
+                let usesGamepad = left.starts_with("gamepad") || right.starts_with("gamepad");
+                let aiLevel = match getOption(args, "--ai") {
+                    Some(name) => {
+                        let level = aiLevelFromName(name);
+                        if level.is_none() {
+                            io::println(fmt!("Unknown ai %s, expected beginner, club, pro or unfair", name));
+                        }
+                        level
+                    }
+                    None if usesAi => chooseAiLevel(&bindings, table.field),
+                    None => Some(Club)
+                };
+                let aiStyle = match getOption(args, "--ai-style") {
+                    Some(name) => {
+                        let style = aiStyleFromName(name);
+                        if style.is_none() {
+                            io::println(fmt!("Unknown ai style %s, expected classic or predictive", name));
+                        }
+                        style
+                    }
+                    None => Some(Predictive)
+                };
+                match (aiLevel, aiStyle) {
+                    (Some(level), Some(style)) => {
+                        let seed = (precise_time_s() as f64 * 1000000.) as u64;
+                        // Two computer players would play exactly the same game if they shared a seed
+                        match (makeController(args, left, level, style, seed), makeController(args, right, level, style, seed + 1)) {
+                            (Some(left), Some(right)) =>
+                                playGame(&bindings, seed, !vec::contains(args, &~"--no-half-field"), rules, table,
+                                         left, right, usesGamepad, getOption(args, "--record")),
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
 
         quit();
     }
@@ -0,0 +1,192 @@
+// Maps keys to the actions of the game. The defaults can be changed in a bindings file:
+//
+//     # Lines starting with # are comments
+//     quit escape
+//     pause p tab
+//     wasd-up i
+//
+// Every line names an action followed by the keys that trigger it, these replace the
+// default keys of that action. Actions are quit, pause, restart, serve-reset, toggle-debug,
+// confirm and the directions of the two keyboard paddles: wasd-up, wasd-down, wasd-left,
+// wasd-right, arrows-up, arrows-down, arrows-left and arrows-right.
+
+use sdl::event::*;
+use airhockeysim::controller::*;
+
+pub enum Direction { Up, Down, Left, Right }
+
+pub enum Action {
+    Quit,
+    Pause,
+    // Start a new match
+    Restart,
+    // Put the puck back in the middle
+    ServeReset,
+    ToggleDebug,
+    // Choose the selected item of a menu, or start the match
+    Confirm,
+    Move(KeyboardLayout, Direction)
+}
+
+impl Eq for Action {
+    fn eq(&self, other: &Action) -> bool {
+        actionName(*self) == actionName(*other)
+    }
+    fn ne(&self, other: &Action) -> bool { !self.eq(other) }
+}
+
+pub fn allActions() -> ~[Action] {
+    ~[Quit, Pause, Restart, ServeReset, ToggleDebug, Confirm,
+      Move(Wasd, Up), Move(Wasd, Down), Move(Wasd, Left), Move(Wasd, Right),
+      Move(Arrows, Up), Move(Arrows, Down), Move(Arrows, Left), Move(Arrows, Right)]
+}
+
+pub fn actionName(action: Action) -> ~str {
+    match action {
+        Quit => ~"quit",
+        Pause => ~"pause",
+        Restart => ~"restart",
+        ServeReset => ~"serve-reset",
+        ToggleDebug => ~"toggle-debug",
+        Confirm => ~"confirm",
+        Move(layout, direction) => {
+            let layoutName = match layout { Wasd => "wasd", Arrows => "arrows" };
+            let directionName = match direction { Up => "up", Down => "down", Left => "left", Right => "right" };
+            fmt!("%s-%s", layoutName, directionName)
+        }
+    }
+}
+
+fn actionFromName(name: &str) -> Option<Action> {
+    for allActions().each |&action| {
+        if actionName(action) == name.to_owned() {
+            return Some(action);
+        }
+    }
+    None
+}
+
+// Names of the keys that can be bound
+fn keyNames() -> ~[(~str, Key)] {
+    ~[(~"a", AKey), (~"b", BKey), (~"c", CKey), (~"d", DKey), (~"e", EKey), (~"f", FKey),
+      (~"g", GKey), (~"h", HKey), (~"i", IKey), (~"j", JKey), (~"k", KKey), (~"l", LKey),
+      (~"m", MKey), (~"n", NKey), (~"o", OKey), (~"p", PKey), (~"q", QKey), (~"r", RKey),
+      (~"s", SKey), (~"t", TKey), (~"u", UKey), (~"v", VKey), (~"w", WKey), (~"x", XKey),
+      (~"y", YKey), (~"z", ZKey),
+      (~"0", Num0Key), (~"1", Num1Key), (~"2", Num2Key), (~"3", Num3Key), (~"4", Num4Key),
+      (~"5", Num5Key), (~"6", Num6Key), (~"7", Num7Key), (~"8", Num8Key), (~"9", Num9Key),
+      (~"up", UpKey), (~"down", DownKey), (~"left", LeftKey), (~"right", RightKey),
+      (~"escape", EscapeKey), (~"space", SpaceKey), (~"return", ReturnKey), (~"tab", TabKey),
+      (~"backspace", BackspaceKey),
+      (~"f1", F1Key), (~"f2", F2Key), (~"f3", F3Key), (~"f4", F4Key), (~"f5", F5Key), (~"f6", F6Key),
+      (~"f7", F7Key), (~"f8", F8Key), (~"f9", F9Key), (~"f10", F10Key), (~"f11", F11Key), (~"f12", F12Key)]
+}
+
+fn keyFromName(name: &str) -> Option<Key> {
+    for keyNames().each |&(ref keyName, key)| {
+        if *keyName == name.to_owned() {
+            return Some(key);
+        }
+    }
+    None
+}
+
+fn keyName(key: Key) -> ~str {
+    for keyNames().each |&(ref name, k)| {
+        if k == key {
+            return copy *name;
+        }
+    }
+    fmt!("%?", key)
+}
+
+pub struct Bindings {
+    bindings: ~[(Key, Action)]
+}
+
+pub fn defaultBindings() -> Bindings {
+    Bindings { bindings: ~[
+        (EscapeKey, Quit),
+        (PKey, Pause),
+        (RKey, Restart),
+        (CKey, ServeReset),
+        (F1Key, ToggleDebug),
+        (ReturnKey, Confirm), (SpaceKey, Confirm),
+        (WKey, Move(Wasd, Up)), (SKey, Move(Wasd, Down)), (AKey, Move(Wasd, Left)), (DKey, Move(Wasd, Right)),
+        (UpKey, Move(Arrows, Up)), (DownKey, Move(Arrows, Down)), (LeftKey, Move(Arrows, Left)), (RightKey, Move(Arrows, Right))
+    ] }
+}
+
+pub impl Bindings {
+    // The action bound to a key, if any
+    fn action(&self, key: Key) -> Option<Action> {
+        for self.bindings.each |&(k, action)| {
+            if k == key {
+                return Some(action);
+            }
+        }
+        None
+    }
+    fn isBound(&self, key: Key, action: Action) -> bool {
+        self.action(key) == Some(action)
+    }
+}
+
+// Reads a bindings file on top of the defaults. Every problem in the
+// file is reported, the bindings are only used when there are none.
+pub fn readBindings(path: &Path) -> Result<Bindings, ~[~str]> {
+    let contents = match io::read_whole_file_str(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(~[e])
+    };
+
+    let mut errors = ~[];
+    // Actions that the file binds, with their keys and the line they are on
+    let mut bound: ~[(Action, ~[Key], uint)] = ~[];
+    for str::lines(contents).eachi |i, line| {
+        let words = str::words(*line);
+        if words.is_empty() || words[0].starts_with("#") { loop; }
+        let number = i + 1;
+
+        let action = match actionFromName(words[0]) {
+            Some(action) => action,
+            None => { errors.push(fmt!("line %u: unknown action %s", number, words[0])); loop; }
+        };
+        if bound.any(|&(a, _, _)| a == action) {
+            errors.push(fmt!("line %u: %s is bound twice", number, words[0]));
+            loop;
+        }
+        if words.len() < 2 {
+            errors.push(fmt!("line %u: %s needs at least one key", number, words[0]));
+            loop;
+        }
+        let mut keys = ~[];
+        for words.tail().each |name| {
+            match keyFromName(*name) {
+                Some(key) => keys.push(key),
+                None => errors.push(fmt!("line %u: unknown key %s", number, *name))
+            }
+        }
+        bound.push((action, keys, number));
+    }
+
+    // Actions that are not in the file keep their default keys
+    let mut bindings = ~[];
+    for defaultBindings().bindings.each |&(key, action)| {
+        if !bound.any(|&(a, _, _)| a == action) {
+            bindings.push((key, action));
+        }
+    }
+    for bound.each |&(action, ref keys, number)| {
+        for keys.each |&key| {
+            for bindings.each |&(k, other)| {
+                if k == key && other != action {
+                    errors.push(fmt!("line %u: %s is already bound to %s", number, keyName(key), actionName(other)));
+                }
+            }
+            bindings.push((key, action));
+        }
+    }
+
+    if errors.is_empty() { Ok(Bindings { bindings: bindings }) } else { Err(errors) }
+}
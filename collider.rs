@@ -0,0 +1,190 @@
+// Static walls of the table: line segments and arcs of circles. Both are solid on either side,
+// and a circle can touch several of them at once, each with its own contact normal.
+
+use vec2::*;
+use physics::*;
+
+pub enum Collider {
+    // A wall from one point to another
+    Segment(Vec2, Vec2),
+    // Part of a circle with a center and a radius, from one direction to another. The arc
+    // turns the way that rotates the x axis onto the y axis. The directions are unit vectors,
+    // so that no trigonometry is needed.
+    Arc(Vec2, f64, Vec2, Vec2)
+}
+
+// Whether a direction lies on the arc from one direction to another
+fn onArc(from:Vec2, to:Vec2, direction:Vec2) -> bool {
+    if from.cross(to) >= 0. { from.cross(direction) >= 0. && direction.cross(to) >= 0. }
+    // Arcs longer than half a circle
+    else { from.cross(direction) >= 0. || direction.cross(to) >= 0. }
+}
+
+// Latest fraction of the step at which a circle moving from a to b, that starts inside
+// a circle around center, reaches its edge. This is how an arc is hit from its inside.
+fn sweptExitTime(a:Vec2, b:Vec2, center:Vec2, radius:f64) -> Option<f64> {
+    let start = a - center;
+    let motion = b - a;
+    let qa = motion.length2();
+    let qb = 2. * start.dot(motion);
+    let qc = start.length2() - radius * radius;
+    if qc > 0. || qa == 0. { return None; }
+    let t = (-qb + f64::sqrt(qb * qb - 4. * qa * qc)) / (2. * qa);
+    if t >= 0. && t <= 1. { Some(t) } else { None }
+}
+
+// Keeps the earliest of two hits
+fn earliestHit(a:Option<(f64, Vec2)>, b:Option<(f64, Vec2)>) -> Option<(f64, Vec2)> {
+    match (a, b) {
+        (Some((ta, na)), Some((tb, nb))) => if tb < ta { Some((tb, nb)) } else { Some((ta, na)) },
+        (Some(x), None) => Some(x),
+        (None, y) => y
+    }
+}
+
+// A circle moving from a to b hitting an end point of a collider
+fn endPointHit(a:Vec2, b:Vec2, radius:f64, point:Vec2) -> Option<(f64, Vec2)> {
+    do sweptCircleTime(a, b, point, point, radius).map |&t| {
+        (t, (a + (b - a) * t - point).normalizeOrZero())
+    }
+}
+
+pub impl Collider {
+    // The point of the collider that is closest to a position
+    fn closestPoint(&self, position:Vec2) -> Vec2 {
+        match *self {
+            Segment(start, end) => {
+                let along = end - start;
+                let length2 = along.length2();
+                if length2 == 0. { return start; }
+                let t = (position - start).dot(along) / length2;
+                start + along * (if t < 0. { 0. } else if t > 1. { 1. } else { t })
+            }
+            Arc(center, radius, from, to) => {
+                let direction = (position - center).normalizeOr(from);
+                if onArc(from, to, direction) { return center + direction * radius; }
+                let first = center + from * radius;
+                let last = center + to * radius;
+                if (position - first).length2() < (position - last).length2() { first } else { last }
+            }
+        }
+    }
+
+    // The normal pointing towards a circle that touches the collider, and how far the circle
+    // has sunk into it
+    fn contact(&self, position:Vec2, radius:f64) -> Option<(Vec2, f64)> {
+        let diff = position - self.closestPoint(position);
+        let distance = diff.length();
+        if distance >= radius { return None; }
+        Some((diff.normalizeOr(XAxis), radius - distance))
+    }
+
+    // Earliest fraction of a move from a to b at which a circle touches the collider,
+    // with the contact normal at that moment
+    fn sweep(&self, a:Vec2, b:Vec2, radius:f64) -> Option<(f64, Vec2)> {
+        match *self {
+            Segment(start, end) => {
+                let mut result = earliestHit(endPointHit(a, b, radius, start), endPointHit(a, b, radius, end));
+                let along = end - start;
+                if along.length2() == 0. { return result; }
+                // The face of the segment on the side the circle comes from
+                let side = along.normalRight().normalize();
+                let normal = if (a - start).dot(side) < 0. { -side } else { side };
+                match sweptWallTime(a, b, radius, normal, normal.dot(start)) {
+                    Some(t) => {
+                        let s = (a + (b - a) * t - start).dot(along);
+                        if s >= 0. && s <= along.length2() {
+                            result = earliestHit(result, Some((t, normal)));
+                        }
+                    }
+                    None => {}
+                }
+                result
+            }
+            Arc(center, arcRadius, from, to) => {
+                let mut result = earliestHit(endPointHit(a, b, radius, center + from * arcRadius),
+                                             endPointHit(a, b, radius, center + to * arcRadius));
+                // Coming from outside the circle, the arc is hit when the circles touch,
+                // from inside when the moving circle reaches the edge
+                let outside = (a - center).length() >= arcRadius;
+                let time = if outside { sweptCircleTime(a, b, center, center, arcRadius + radius) }
+                           else if arcRadius > radius { sweptExitTime(a, b, center, arcRadius - radius) }
+                           else { None };
+                match time {
+                    Some(t) => {
+                        let direction = (a + (b - a) * t - center).normalizeOr(from);
+                        if onArc(from, to, direction) {
+                            result = earliestHit(result, Some((t, if outside { direction } else { -direction })));
+                        }
+                    }
+                    None => {}
+                }
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn near(a: f64, b: f64) -> bool {
+    f64::abs(a - b) < 1e-9
+}
+
+#[test]
+fn contactInCorner() {
+    // Two walls meeting in a square corner, each pushes the puck out along its own normal
+    let walls = [Segment(Vec2(0., 0.), Vec2(100., 0.)), Segment(Vec2(0., 0.), Vec2(0., 100.))];
+    let position = Vec2(20., 25.);
+    match walls[0].contact(position, 30.) {
+        Some((normal, depth)) => assert!(normal.x == 0. && normal.y == 1. && depth == 5.),
+        None => fail!(~"no contact with the top wall")
+    }
+    match walls[1].contact(position, 30.) {
+        Some((normal, depth)) => assert!(normal.x == 1. && normal.y == 0. && depth == 10.),
+        None => fail!(~"no contact with the left wall")
+    }
+    assert!(walls[0].contact(Vec2(50., 30.), 30.).is_none());
+}
+
+#[test]
+fn sweepSegmentEndPoint() {
+    // Moving along the line of the segment only hits its end
+    let wall = Segment(Vec2(0., 0.), Vec2(100., 0.));
+    match wall.sweep(Vec2(-30., 0.), Vec2(0., 0.), 10.) {
+        Some((t, normal)) => assert!(near(t, 2. / 3.) && normal.x == -1. && normal.y == 0.),
+        None => fail!(~"missed the end of the segment")
+    }
+    // Coming from below hits the face
+    match wall.sweep(Vec2(50., -30.), Vec2(50., 10.), 10.) {
+        Some((t, normal)) => assert!(near(t, 0.5) && normal.x == 0. && normal.y == -1.),
+        None => fail!(~"missed the face of the segment")
+    }
+}
+
+#[test]
+fn sweepArc() {
+    // A quarter circle from the x axis to the y axis
+    let arc = Arc(Vec2(0., 0.), 50., Vec2(1., 0.), Vec2(0., 1.));
+    let diagonal = f64::sqrt(0.5);
+    // From outside the circles touch when the puck is 60 from the center
+    match arc.sweep(Vec2(60., 60.), Vec2(20., 20.), 10.) {
+        Some((t, normal)) => assert!(near(t, (60. - 60. * diagonal) / 40.) && near(normal.x, diagonal) && near(normal.y, diagonal)),
+        None => fail!(~"missed the arc from outside")
+    }
+    // From inside the puck reaches the arc 40 from the center
+    match arc.sweep(Vec2(0., 0.), Vec2(40., 40.), 10.) {
+        Some((t, normal)) => assert!(near(t, 40. * diagonal / 40.) && near(normal.x, -diagonal) && near(normal.y, -diagonal)),
+        None => fail!(~"missed the arc from inside")
+    }
+    // The other quarters of the circle are open
+    assert!(arc.sweep(Vec2(0., 0.), Vec2(-40., -40.), 10.).is_none());
+}
+
+#[test]
+fn halfCircleArc() {
+    // Opposite directions have a cross product of zero, the arc is the half turning from x to y
+    assert!(onArc(Vec2(1., 0.), Vec2(-1., 0.), Vec2(0., 1.)));
+    assert!(!onArc(Vec2(1., 0.), Vec2(-1., 0.), Vec2(0., -1.)));
+    assert!(onArc(Vec2(-1., 0.), Vec2(1., 0.), Vec2(0., -1.)));
+    assert!(!onArc(Vec2(-1., 0.), Vec2(1., 0.), Vec2(0., 1.)));
+}
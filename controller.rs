@@ -0,0 +1,290 @@
+// Controllers decide what a paddle does. They only get to look at the game, so
+// either side of the table can be driven by any controller: a human on the mouse,
+// keyboard or gamepad, the computer, or a script.
+
+use vec2::*;
+use game::*;
+use ai::*;
+
+pub struct Directions { up: bool, down: bool, left: bool, right: bool }
+
+pub fn Directions() -> Directions {
+    Directions { up: false, down: false, left: false, right: false }
+}
+
+pub impl Directions {
+    // Unit vector in the held direction, or zero
+    fn vector(&self) -> Vec2 {
+        let x = (if self.right { 1. } else { 0. }) - (if self.left { 1. } else { 0. });
+        let y = (if self.down { 1. } else { 0. }) - (if self.up { 1. } else { 0. });
+        Vec2(x, y).normalizeOrZero()
+    }
+}
+
+pub enum KeyboardLayout { Wasd, Arrows }
+
+// State of the input devices, kept up to date by the front end
+pub struct Devices {
+    mouse: Vec2,
+    wasd: Directions,
+    arrows: Directions,
+    // Position of the stick of each gamepad, both axes in [-1, 1]
+    gamepads: ~[Vec2]
+}
+
+// Raw value of an axis as reported by SDL
+pub fn axisValue(value: i16) -> f64 {
+    f64::max(-1., (value as f64) / 32767.)
+}
+
+// Updates the stick of a gamepad from an axis event. Only the first two axes, the
+// left stick, are used. Gamepads are numbered as SDL opens them.
+pub fn setGamepadAxis(devices: &mut Devices, gamepad: uint, axis: uint, value: i16) {
+    while devices.gamepads.len() <= gamepad {
+        devices.gamepads.push(Zero);
+    }
+    match axis {
+        0 => devices.gamepads[gamepad].x = axisValue(value),
+        1 => devices.gamepads[gamepad].y = axisValue(value),
+        _ => {}
+    }
+}
+
+pub fn Devices() -> Devices {
+    Devices { mouse: Zero, wasd: Directions(), arrows: Directions(), gamepads: ~[] }
+}
+
+pub trait Controller {
+    // Called once for every step, before the game is updated
+    fn control(&mut self, game: &Game, side: Side, devices: &Devices) -> Command;
+}
+
+// The paddle follows the mouse cursor
+pub struct MouseController;
+
+impl Controller for MouseController {
+    fn control(&mut self, _: &Game, _: Side, devices: &Devices) -> Command {
+        MoveTo(devices.mouse)
+    }
+}
+
+// The paddle accelerates in the direction of the held keys
+pub struct KeyboardController {
+    layout: KeyboardLayout,
+    // In units per second
+    maxSpeed: f64,
+    // In units per second squared
+    acceleration: f64
+}
+
+pub fn KeyboardController(layout: KeyboardLayout) -> KeyboardController {
+    KeyboardController { layout: layout, maxSpeed: 600., acceleration: 4000. }
+}
+
+impl Controller for KeyboardController {
+    fn control(&mut self, game: &Game, side: Side, devices: &Devices) -> Command {
+        let directions = match self.layout {
+            Wasd => devices.wasd,
+            Arrows => devices.arrows
+        };
+        let velocity = game.paddle(side).velocity;
+        let change = directions.vector() * self.maxSpeed - velocity;
+        let maxChange = self.acceleration * TimeStep;
+        MoveWith(velocity + if change.length() > maxChange { change.normalizeOrZero() * maxChange } else { change })
+    }
+}
+
+// Either the stick sets the velocity of the paddle, or it moves the paddle
+// away from the middle of its half, like the mouse does
+pub enum StickMode { StickVelocity, StickOffset }
+
+pub struct StickSettings {
+    mode: StickMode,
+    // Part of the stick travel around the center that is ignored, so that a worn stick does not drift
+    deadzone: f64,
+    // Exponent of the response curve: 1 is linear, larger values give finer control near the center
+    curve: f64
+}
+
+pub fn defaultStickSettings() -> StickSettings {
+    StickSettings { mode: StickVelocity, deadzone: 0.15, curve: 2. }
+}
+
+// Applies the deadzone and the response curve to a raw stick position.
+// The deadzone is radial, so diagonals are not cut off, and the remaining travel
+// is stretched so that the output still starts at zero and ends at one.
+pub fn stickResponse(stick: Vec2, settings: &StickSettings) -> Vec2 {
+    let length = f64::min(stick.length(), 1.);
+    if length <= settings.deadzone || settings.deadzone >= 1. {
+        return Zero;
+    }
+    let scaled = (length - settings.deadzone) / (1. - settings.deadzone);
+    stick.normalizeOrZero() * f64::pow(scaled, settings.curve)
+}
+
+pub struct GamepadController {
+    index: uint,
+    settings: StickSettings,
+    // In units per second, at full stick deflection
+    maxSpeed: f64
+}
+
+pub fn GamepadController(index: uint, settings: StickSettings) -> GamepadController {
+    GamepadController { index: index, settings: settings, maxSpeed: 800. }
+}
+
+impl Controller for GamepadController {
+    fn control(&mut self, game: &Game, side: Side, devices: &Devices) -> Command {
+        let stick = if self.index < devices.gamepads.len() { devices.gamepads[self.index] } else { Zero };
+        let response = stickResponse(stick, &self.settings);
+        match self.settings.mode {
+            StickVelocity => MoveWith(response * self.maxSpeed),
+            StickOffset => {
+                let center = match side {
+                    LeftSide => Vec2(game.field.x * 0.25, game.field.y * 0.5),
+                    RightSide => Vec2(game.field.x * 0.75, game.field.y * 0.5)
+                };
+                MoveTo(center + Vec2(response.x * game.field.x * 0.25, response.y * game.field.y * 0.5))
+            }
+        }
+    }
+}
+
+// Plays a fixed list of commands, one per step, and keeps repeating the last one
+pub struct ScriptedController {
+    commands: ~[Command],
+    position: uint
+}
+
+pub fn ScriptedController(commands: ~[Command]) -> ScriptedController {
+    ScriptedController { commands: commands, position: 0 }
+}
+
+impl Controller for ScriptedController {
+    fn control(&mut self, _: &Game, _: Side, _: &Devices) -> Command {
+        if self.commands.is_empty() { return MoveWith(Zero); }
+        let command = self.commands[uint::min(self.position, self.commands.len() - 1)];
+        self.position += 1;
+        command
+    }
+}
+
+// Creates a controller from its command line name: mouse, wasd, arrows, gamepad,
+// gamepad2 or ai. The ai uses the given level and style, and its own seeded randomness.
+pub fn controllerFromName(name: &str, aiLevel: AiLevel, aiStyle: AiStyle, seed: u64) -> Option<~Controller> {
+    match name {
+        "mouse" => Some(~MouseController as ~Controller),
+        "wasd" => Some(~KeyboardController(Wasd) as ~Controller),
+        "arrows" => Some(~KeyboardController(Arrows) as ~Controller),
+        "gamepad" => Some(~GamepadController(0, defaultStickSettings()) as ~Controller),
+        "gamepad2" => Some(~GamepadController(1, defaultStickSettings()) as ~Controller),
+        "ai" => Some(~AiController(aiLevel, aiStyle, seed) as ~Controller),
+        _ => None
+    }
+}
+
+// Asks both controllers what to do and advances the game by a single step.
+// Returns the inputs that were used, so that they can be recorded.
+pub fn stepControllers(game: &mut Game, left: &mut ~Controller, right: &mut ~Controller, devices: &Devices) -> Inputs {
+    let inputs = Inputs {
+        left: left.control(game, LeftSide, devices),
+        right: right.control(game, RightSide, devices)
+    };
+    step(game, inputs);
+    inputs
+}
+
+#[cfg(test)]
+fn near(a: f64, b: f64) -> bool {
+    f64::abs(a - b) < 1e-9
+}
+
+#[test]
+fn gamepadAxesSetTheStick() {
+    let mut devices = Devices();
+    setGamepadAxis(&mut devices, 1, 0, 32767);
+    setGamepadAxis(&mut devices, 1, 1, -32768);
+    assert!(devices.gamepads.len() == 2);
+    assert!(devices.gamepads[1].x == 1. && devices.gamepads[1].y == -1.);
+    // Axes past the left stick are ignored, but still make room for the gamepad
+    setGamepadAxis(&mut devices, 2, 5, 1000);
+    assert!(devices.gamepads.len() == 3);
+    assert!(devices.gamepads[2].x == 0. && devices.gamepads[2].y == 0.);
+}
+
+#[test]
+fn stickResponseDeadzone() {
+    let settings = defaultStickSettings();
+    assert!(stickResponse(Vec2(0.15, 0.), &settings).length() == 0.);
+    assert!(stickResponse(Vec2(0., -0.1), &settings).length() == 0.);
+    assert!(stickResponse(Vec2(0.16, 0.), &settings).x > 0.);
+}
+
+#[test]
+fn stickResponseFullDeflection() {
+    let settings = defaultStickSettings();
+    let mut devices = Devices();
+    setGamepadAxis(&mut devices, 0, 0, -32768);
+    let response = stickResponse(devices.gamepads[0], &settings);
+    assert!(near(response.x, -1.) && response.y == 0.);
+    // A diagonal reaches full speed as well, not more
+    setGamepadAxis(&mut devices, 0, 0, 32767);
+    setGamepadAxis(&mut devices, 0, 1, 32767);
+    let diagonal = stickResponse(devices.gamepads[0], &settings);
+    assert!(near(diagonal.length(), 1.) && near(diagonal.x, diagonal.y));
+}
+
+#[test]
+fn stickResponseCurve() {
+    // Halfway between the deadzone and full deflection
+    let stick = Vec2(0.575, 0.);
+    let mut settings = defaultStickSettings();
+    assert!(near(stickResponse(stick, &settings).x, 0.25));
+    settings.curve = 1.;
+    assert!(near(stickResponse(stick, &settings).x, 0.5));
+    settings.curve = 3.;
+    assert!(near(stickResponse(stick, &settings).x, 0.125));
+}
+
+// Repeats a pattern of commands, each given with the number of steps it is held for
+#[cfg(test)]
+fn repeatScript(pattern: &[(uint, Command)], times: uint) -> ~[Command] {
+    let mut commands = ~[];
+    for uint::range(0, times) |_| {
+        for pattern.each |&(count, command)| {
+            for uint::range(0, count) |_| {
+                commands.push(command);
+            }
+        }
+    }
+    commands
+}
+
+// Plays a scripted match until it is over, for at most 3000 steps, and returns the state
+// hash after every step
+#[cfg(test)]
+fn playScriptedMatch(seed: u64) -> ~[u64] {
+    let left = repeatScript([(45, MoveWith(Vec2(900., 300.))), (45, MoveWith(Vec2(-600., -500.))),
+                             (30, MoveWith(Vec2(800., -200.))), (30, MoveWith(Vec2(-500., 600.)))], 20);
+    let right = repeatScript([(40, MoveWith(Vec2(-1000., -200.))), (50, MoveWith(Vec2(700., 400.))),
+                              (35, MoveWith(Vec2(-900., 450.))), (35, MoveWith(Vec2(600., -650.)))], 20);
+    let mut left = ~ScriptedController(left) as ~Controller;
+    let mut right = ~ScriptedController(right) as ~Controller;
+    let mut game = setupGame(seed);
+    let devices = Devices();
+    let mut hashes = ~[];
+    for uint::range(0, 3000) |_| {
+        if game.clock.over { break; }
+        stepControllers(game, &mut left, &mut right, &devices);
+        hashes.push(hashGame(game));
+    }
+    hashes
+}
+
+#[test]
+fn scriptedMatchIsReproducible() {
+    let first = playScriptedMatch(1);
+    assert!(first == playScriptedMatch(1));
+    // The seed decides the face-off, so another seed differs from the first step on
+    assert!(first[0] != playScriptedMatch(2)[0]);
+}
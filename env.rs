@@ -0,0 +1,95 @@
+// The simulation as a reinforcement learning environment. The agent controls the
+// left paddle by choosing its velocity every step, the right paddle is a built-in AI.
+
+use vec2::*;
+use game::*;
+use ai::*;
+use controller::*;
+
+// Number of values in an observation:
+// puck, player and opponent each with position and velocity, then both scores
+pub static ObservationSize: uint = 14;
+
+pub struct Env {
+    game: ~Game,
+    level: AiLevel,
+    style: AiStyle,
+    opponent: ~Controller,
+    // Steps since the last reset
+    steps: uint,
+    // An episode ends after this many steps
+    maxSteps: uint
+}
+
+pub fn Env(level: AiLevel, style: AiStyle, maxSteps: uint) -> Env {
+    Env {
+        game: setupGame(0),
+        level: level,
+        style: style,
+        opponent: ~AiController(level, style, 0) as ~Controller,
+        steps: 0,
+        maxSteps: maxSteps
+    }
+}
+
+pub impl Env {
+    // Starts a new episode and returns the first observation
+    fn reset(&mut self, seed: u64) -> ~[f64] {
+        self.game = setupGame(seed);
+        self.opponent = ~AiController(self.level, self.style, seed) as ~Controller;
+        self.steps = 0;
+        self.observe()
+    }
+
+    // Moves the agent paddle with the given velocity for one step.
+    // Returns the observation afterwards, the reward and whether the episode is over,
+    // which is when the match has been decided or after maxSteps.
+    fn step(&mut self, action: Vec2) -> (~[f64], f64, bool) {
+        let opponent = self.opponent.control(self.game, RightSide, &Devices());
+        step(self.game, Inputs { left: MoveWith(action), right: opponent });
+        self.steps += 1;
+
+        // A goal for the agent is worth one point, a goal against costs one
+        let mut reward = 0.;
+        for self.game.events.each |&event| {
+            match event {
+                GoalScored(LeftSide) => { reward += 1.; }
+                GoalScored(RightSide) => { reward -= 1.; }
+                _ => {}
+            }
+        }
+        (self.observe(), reward, self.game.clock.over || self.steps >= self.maxSteps)
+    }
+
+    fn observe(&self) -> ~[f64] {
+        observation(self.game, LeftSide)
+    }
+}
+
+// The observation of the given side. The right side sees a mirrored table, so that
+// a policy trained on the left side can play on either side.
+pub fn observation(game: &Game, side: Side) -> ~[f64] {
+    let (own, other, ownScore, otherScore) = match side {
+        LeftSide => (game.player, game.opponent, game.playerScore, game.opponentScore),
+        RightSide => (game.opponent, game.player, game.opponentScore, game.playerScore)
+    };
+    let mut observation = vec::with_capacity(ObservationSize);
+    for [&**game.puck, &**own, &**other].each |&circle| {
+        match side {
+            LeftSide => {
+                observation.push(circle.position.x);
+                observation.push(circle.position.y);
+                observation.push(circle.velocity.x);
+            }
+            RightSide => {
+                observation.push(game.field.x - circle.position.x);
+                observation.push(circle.position.y);
+                observation.push(-circle.velocity.x);
+            }
+        }
+        observation.push(circle.velocity.y);
+    }
+    observation.push(ownScore as f64);
+    observation.push(otherScore as f64);
+    observation
+}
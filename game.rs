@@ -0,0 +1,574 @@
+use vec2::*;
+use pendinglist::*;
+use physics::*;
+use random::*;
+use rules::*;
+use goal::*;
+use table::*;
+use collider::*;
+
+// The simulation always advances in steps of this size (in seconds), independent of the frame rate
+pub static TimeStep: f64 = 1. / 120.;
+// Largest speed along each axis of the push the puck gets at the face-off
+static FaceOffSpeed: f64 = 100.;
+
+pub trait GameObject {
+    fn update(&mut self, physics: &PhysicsParams, dt: f64);
+}
+
+pub struct Paddle(PhysicalCircle);
+fn newPaddle(position:Vec2, spec:&CircleSpec) -> @mut Paddle {
+    @mut Paddle(newCircle(position, spec.radius, spec.mass, spec.restitution, spec.friction))
+}
+
+impl GameObject for Paddle {
+    fn update(&mut self, _: &PhysicsParams, dt: f64) {
+        self.previousPosition = self.position;
+        self.position += self.velocity * dt;
+        // Paddles are held by the players, so they never keep any spin
+        self.angularVelocity = 0.;
+    }
+}
+
+pub struct Puck(PhysicalCircle);
+fn newPuck(position:Vec2, spec:&CircleSpec) -> @mut Puck {
+    @mut Puck(newCircle(position, spec.radius, spec.mass, spec.restitution, spec.friction))
+}
+impl GameObject for Puck {
+    fn update(&mut self, physics: &PhysicsParams, dt: f64) {
+        self.previousPosition = self.position;
+        self.previousAngle = self.angle;
+
+        // Limit velocity of puck
+        let maxSpeed = physics.puckMaxSpeed;
+        let speed = self.velocity.length();
+        let direction = self.velocity.normalizeOrZero();
+        self.velocity = direction * if speed > maxSpeed { maxSpeed } else { speed };
+
+        // Apply velocity
+        self.position += self.velocity * dt;
+        self.angle += self.angularVelocity * dt;
+
+        // Apply damping
+        self.velocity *= decay(dt, physics.puckDamping);
+        self.angularVelocity *= decay(dt, physics.puckSpinDamping);
+
+    }
+}
+
+// Obstacles are paddles that cannot be moved
+fn newObstacle(obstacle:&Obstacle) -> @mut Paddle {
+    @mut Paddle(newCircle(obstacle.position, obstacle.radius, f64::infinity, obstacle.restitution, 0.3))
+}
+
+pub enum Side { LeftSide, RightSide }
+
+impl Eq for Side {
+    fn eq(&self, other: &Side) -> bool {
+        match (*self, *other) {
+            (LeftSide, LeftSide) | (RightSide, RightSide) => true,
+            _ => false
+        }
+    }
+    fn ne(&self, other: &Side) -> bool { !self.eq(other) }
+}
+
+pub fn opposite(side: Side) -> Side {
+    match side {
+        LeftSide => RightSide,
+        RightSide => LeftSide
+    }
+}
+
+// Things that happened during a step, for anyone watching the game
+pub enum GameEvent {
+    // The given side scored
+    GoalScored(Side),
+    // A timed period ended, or the regular time ended in a tie and overtime starts
+    PeriodEnded,
+    // The given side kept the puck on its half for too long
+    Foul(Side),
+    // The winner, None for a draw
+    MatchEnded(Option<Side>)
+}
+
+pub struct Game {
+    objects: PendingList<@GameObject>,
+    player: @mut Paddle,
+    playerScore: uint,
+    opponent: @mut Paddle,
+    opponentScore: uint,
+    puck: @mut Puck,
+    paddles: ~[@mut Paddle],
+    field: Vec2,
+    // The left goal first, then the right one
+    goals: ~[Goal],
+    // Where the puck is put back in play
+    puckSpawn: Vec2,
+    // Everything the puck bounces off that is not a paddle or an obstacle
+    walls: ~[Collider],
+    wallRestitution: f64,
+    wallFriction: f64,
+    physics: PhysicsParams,
+    // Keep the player and the opponent on their own half of the field
+    halfFieldRule: bool,
+    // The only source of randomness in the simulation
+    rng: Rng,
+    // Events of the last step
+    events: ~[GameEvent],
+    rules: MatchRules,
+    clock: MatchClock,
+    serve: ServeState
+}
+
+pub impl Game {
+    // The player is on the left side, the opponent on the right side
+    fn paddle(&self, side: Side) -> @mut Paddle {
+        match side {
+            LeftSide => self.player,
+            RightSide => self.opponent
+        }
+    }
+    // The goal a side defends
+    fn goal(&self, side: Side) -> Goal {
+        self.goals.find(|goal| goal.side == side).get()
+    }
+}
+
+// What a controller wants its paddle to do during a step
+pub enum Command {
+    // Steer towards a position, like the mouse does
+    MoveTo(Vec2),
+    // Move with exactly this velocity, in units per second
+    MoveWith(Vec2)
+}
+
+// Everything the players control during a single step
+pub struct Inputs {
+    left: Command,
+    right: Command
+}
+
+fn updateGame(game:&mut Game, dt:f64) {
+    let physics = game.physics;
+    for game.objects.each_mut |object| {
+        object.update(&physics, dt);
+    }
+}
+
+fn handleControls(game:&mut Game, inputs:Inputs, dt:f64) {
+    drivePaddle(game, game.player, inputs.left, dt);
+    drivePaddle(game, game.opponent, inputs.right, dt);
+}
+
+fn drivePaddle(game:&Game, paddle:@mut Paddle, command:Command, dt:f64) {
+    let physics = game.physics;
+    let velocity = match command {
+        MoveTo(target) => {
+            let desiredVelocity = velocityTowards(paddle.position, target, physics.paddleMaxSpeed, physics.paddleArrival);
+            approach(paddle.velocity, desiredVelocity, dt, physics.targetResponse)
+        }
+        MoveWith(velocity) => velocity
+    };
+    let speed = velocity.length();
+    paddle.velocity = if speed > physics.paddleMaxSpeed { velocity * (physics.paddleMaxSpeed / speed) } else { velocity };
+}
+
+// A collision found by sweeping the puck along its path
+struct Impact {
+    // Fraction of the swept path at which contact occurs
+    time: f64,
+    // Surface normal at the contact, pointing towards the puck
+    normal: Vec2,
+    // The paddle that was hit, or None for a wall
+    paddle: Option<@mut Paddle>
+}
+
+fn earliestImpact(a:Option<Impact>, b:Option<Impact>) -> Option<Impact> {
+    match (a, b) {
+        (Some(x), Some(y)) => if y.time < x.time { Some(y) } else { Some(x) },
+        (Some(x), None) => Some(x),
+        (None, y) => y
+    }
+}
+
+// Finds the first paddle or wall the puck hits when moving from `from` to its current position.
+// `start` is the fraction of the step that has already been simulated.
+fn findImpact(game:&Game, from:Vec2, start:f64) -> Option<Impact> {
+    let puck = game.puck;
+    let mut result = getSurfaceImpact(game, from, puck.position, puck.radius);
+    for game.paddles.each |paddle| {
+        let paddleFrom = paddle.interpolatedPosition(start);
+        match sweptCircleTime(from, puck.position, paddleFrom, paddle.position, puck.radius + paddle.radius) {
+            Some(t) => {
+                let puckContact = from + (puck.position - from) * t;
+                let paddleContact = paddleFrom + (paddle.position - paddleFrom) * t;
+                result = earliestImpact(result, Some(Impact {
+                    time: t,
+                    normal: (puckContact - paddleContact).normalizeOrZero(),
+                    paddle: Some(*paddle)
+                }));
+            }
+            None => {}
+        }
+    }
+    result
+}
+
+fn handleCollision(game:&mut Game, dt:f64) {
+    // Resolve the impacts along the path of the puck in order, so that fast pucks cannot tunnel
+    let maxImpacts = 4;
+    let mut from = game.puck.previousPosition;
+    let mut start = 0.;
+    for uint::range(0, maxImpacts) |_| {
+        match findImpact(game, from, start) {
+            Some(impact) => {
+                let contact = from + (game.puck.position - from) * impact.time;
+                start += (1. - start) * impact.time;
+                match impact.paddle {
+                    Some(paddle) => collide(&mut **game.puck, &mut **paddle, impact.normal),
+                    None => collideWall(&mut **game.puck, impact.normal, game.wallRestitution, game.wallFriction)
+                }
+                // Continue with the new velocity for the remainder of the step
+                game.puck.position = contact + game.puck.velocity * ((1. - start) * dt);
+                from = contact;
+            }
+            None => { break; }
+        }
+    }
+
+    // Handle paddle - puck collision
+    for game.paddles.each |&paddle| {
+        let diff = (game.puck.position - paddle.position);
+        if (diff.length() < game.puck.radius+paddle.radius) {
+            collide(&mut **game.puck, &mut **paddle, diff.normalizeOrZero());
+            match getSeparation(&**game.puck, &**paddle) {
+                Some((puckOffset, paddleOffset)) => {
+                    game.puck.position += puckOffset;
+                    paddle.position += paddleOffset;
+                }
+                None => {}
+            }
+        }
+    };
+    // Handle field boundaries - puck collision
+    let puck = game.puck;
+    handleWallCollision(game, &mut *puck);
+}
+
+// Bounces a puck off the walls it touches and pushes it out of them. Each wall is checked
+// after the puck was pushed out of the previous ones, so that two walls that meet in a corner
+// do not both push the puck away from the same spot.
+pub fn handleWallCollision(game:&Game, puck:&mut Puck) {
+    for game.walls.each |wall| {
+        match wall.contact(puck.position, puck.radius) {
+            Some((normal, depth)) => {
+                collideWall(&mut **puck, normal, game.wallRestitution, game.wallFriction);
+                puck.position += normal * depth;
+            }
+            None => {}
+        }
+    }
+}
+
+// Bounces a puck off the obstacles it touches. Only the puck is changed, so that the AI can
+// use this to predict where the puck goes.
+pub fn handleObstacleCollision(game:&Game, puck:&mut Puck) {
+    for game.paddles.eachi |i, &paddle| {
+        // The first two are the player and the opponent
+        if i < 2 { loop; }
+        let mut obstacle = *paddle;
+        let diff = puck.position - obstacle.position;
+        let overlap = puck.radius + obstacle.radius - diff.length();
+        if overlap > 0. {
+            let normal = diff.normalizeOr(XAxis);
+            collide(&mut **puck, &mut *obstacle, normal);
+            puck.position += normal * overlap;
+        }
+    }
+}
+
+fn handlePaddleCollision(game:&mut Game) {
+    // Handle paddle - paddle and paddle - pole collision
+    let count = game.paddles.len();
+    for uint::range(0, count) |i| {
+        for uint::range(i + 1, count) |j| {
+            let a = game.paddles[i];
+            let b = game.paddles[j];
+            let diff = a.position - b.position;
+            if (diff.length() < a.radius+b.radius) {
+                collide(&mut **a, &mut **b, diff.normalizeOr(XAxis));
+                match getSeparation(&**a, &**b) {
+                    Some((aOffset, bOffset)) => {
+                        a.position += aOffset;
+                        b.position += bOffset;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    // Handle field boundaries - paddle collision
+    containPaddle(game, game.player, LeftSide);
+    containPaddle(game, game.opponent, RightSide);
+}
+
+fn containPaddle(game:&Game, paddle:@mut Paddle, side:Side) {
+    let r = paddle.radius;
+    // During the countdown after a restart the paddles stay on their own half, whatever the rule
+    let ownHalf = game.halfFieldRule || game.serve.countdown > 0.;
+    let min = Vec2(match side {
+        RightSide if ownHalf => game.field.x*0.5 + r,
+        _ => r
+    }, r);
+    let max = Vec2(match side {
+        LeftSide if ownHalf => game.field.x*0.5 - r,
+        _ => game.field.x - r
+    }, game.field.y - r);
+    let (x, vx) = clampAxis(paddle.position.x, paddle.velocity.x, min.x, max.x);
+    let (y, vy) = clampAxis(paddle.position.y, paddle.velocity.y, min.y, max.y);
+    paddle.position = Vec2(x, y);
+    paddle.velocity = Vec2(vx, vy);
+
+    // Rounded corners and walls inside the field stop the paddle as well
+    for game.walls.each |wall| {
+        match wall.contact(paddle.position, r) {
+            Some((normal, depth)) => {
+                paddle.position += normal * depth;
+                let into = paddle.velocity.dot(normal);
+                if into < 0. { paddle.velocity = paddle.velocity - normal * into; }
+            }
+            None => {}
+        }
+    }
+}
+
+// Velocity that moves from source to destination at the given speed,
+// slowing down so that the remaining distance would be covered in `arrival` seconds
+pub fn velocityTowards(source:Vec2, destination:Vec2, speed:f64, arrival:f64) -> Vec2 {
+    let diff = destination - source;
+    let distance = diff.length();
+    let direction = diff.normalizeOrZero();
+    if distance < speed * arrival { direction * (distance / arrival) }
+    else { direction * speed }
+}
+
+// Puts the puck back at its spawn point, at rest
+pub fn resetServe(game:&mut Game) {
+    game.puck.teleport(game.puckSpawn);
+    game.puck.velocity = Zero;
+    game.puck.angularVelocity = 0.;
+    game.serve.puckSideTime = 0.;
+}
+
+// A goal counts once the whole puck is behind the goal line.
+// The side that conceded the goal serves. Once the match is over nothing counts any more.
+fn handleGoals(game:&mut Game) {
+    if game.clock.over { return; }
+    let p = game.puck;
+    let mut conceding = None;
+    for game.goals.each |goal| {
+        if goal.contains(p.position, p.radius) { conceding = Some(goal.side); }
+    }
+    match conceding {
+        Some(LeftSide) => {
+            game.opponentScore = game.opponentScore + 1;
+            game.events.push(GoalScored(RightSide));
+            awardServe(game, LeftSide);
+        }
+        Some(RightSide) => {
+            game.playerScore = game.playerScore + 1;
+            game.events.push(GoalScored(LeftSide));
+            awardServe(game, RightSide);
+        }
+        None => {}
+    }
+}
+
+// The first wall hit when a circle moves from a to b
+fn getSurfaceImpact(game: &Game, a:Vec2, b:Vec2, radius:f64) -> Option<Impact> {
+    let mut result = None;
+    for game.walls.each |wall| {
+        match wall.sweep(a, b, radius) {
+            Some((t, normal)) => result = earliestImpact(result, Some(Impact { time: t, normal: normal, paddle: None })),
+            None => {}
+        }
+    }
+    result
+}
+
+// Games set up with the same seed and stepped with the same inputs end up in exactly the same state
+pub fn setupGame(seed: u64) -> ~Game {
+    setupGameOnTable(seed, &defaultTable())
+}
+
+pub fn setupGameOnTable(seed: u64, table: &Table) -> ~Game {
+    let mut player = newPaddle(table.leftSpawn, &table.paddle);
+    let mut opponent = newPaddle(table.rightSpawn, &table.paddle);
+    let mut puck = newPuck(table.puckSpawn, &table.puck);
+
+    let mut paddles = ~[player, opponent];
+    for table.obstacles.each |obstacle| {
+        paddles.push(newObstacle(obstacle));
+    }
+
+    let mut game = ~Game {
+        objects: PendingList(),
+        field: table.field,
+        goals: copy table.goals,
+        walls: copy table.walls,
+        puckSpawn: table.puckSpawn,
+        wallRestitution: 1.,
+        wallFriction: 0.1,
+        physics: defaultPhysicsParams(),
+        halfFieldRule: true,
+        rng: Rng(seed),
+        events: ~[],
+        rules: defaultMatchRules(),
+        clock: MatchClock(&defaultMatchRules()),
+        serve: ServeState(&defaultMatchRules()),
+        player: player,
+        playerScore: 0,
+        opponent: opponent,
+        opponentScore: 0,
+        puck: puck,
+        paddles: paddles
+    };
+
+    addPaddles(game);
+    game.objects.add(puck as @GameObject);
+    game.objects.handlePending();
+    faceOff(game);
+
+    game
+}
+
+// The puck starts with a small push in a direction drawn from the seed,
+// so that games with different seeds play out differently from the start
+fn faceOff(game:&mut Game) {
+    let push = Vec2(game.rng.range(-1., 1.), game.rng.range(-1., 1.)) * FaceOffSpeed;
+    game.puck.velocity = push;
+}
+
+// Changes the rules, before the match starts
+pub fn setRules(game:&mut Game, rules:MatchRules) {
+    game.rules = rules;
+    game.clock = MatchClock(&rules);
+    game.serve = ServeState(&rules);
+}
+
+fn addPaddles(game:&mut Game) {
+    for game.paddles.each |&paddle| {
+        game.objects.add(paddle as @GameObject);
+    }
+}
+
+// Advances the game by a single TimeStep
+pub fn step(game: &mut Game, inputs: Inputs) {
+    let dt = TimeStep;
+    game.events = ~[];
+    handleControls(game, inputs, dt);
+    updateGame(game, dt);
+    handlePaddleCollision(game);
+    handleCollision(game, dt);
+    handleGoals(game);
+    handleServe(game, dt);
+    handleRules(game, dt);
+    game.objects.handlePending();
+}
+
+// FNV-1a hash, fed with the exact bits of the simulation state
+struct StateHash { value: u64 }
+impl StateHash {
+    fn addBits(&mut self, bits: u64) {
+        for uint::range(0, 8) |i| {
+            self.value = (self.value ^ ((bits >> (i * 8)) & 0xff)) * 0x100000001b3u64;
+        }
+    }
+    fn addFloat(&mut self, x: f64) {
+        self.addBits(unsafe { cast::transmute::<f64, u64>(x) });
+    }
+    fn addCircle(&mut self, c: &PhysicalCircle) {
+        self.addFloat(c.position.x);
+        self.addFloat(c.position.y);
+        self.addFloat(c.velocity.x);
+        self.addFloat(c.velocity.y);
+        self.addFloat(c.angle);
+        self.addFloat(c.angularVelocity);
+    }
+}
+
+// Hash of the complete dynamic state of the game, including the clock and the serve, to check
+// that simulations are reproducible
+pub fn hashGame(game: &Game) -> u64 {
+    let mut hash = StateHash { value: 0xcbf29ce484222325u64 };
+    for game.paddles.each |&paddle| {
+        hash.addCircle(&**paddle);
+    }
+    hash.addCircle(&**game.puck);
+    hash.addBits(game.playerScore as u64);
+    hash.addBits(game.opponentScore as u64);
+    hash.addBits(game.rng.state);
+    hash.addBits(game.clock.period as u64);
+    hash.addFloat(game.clock.remaining);
+    hash.addBits(game.clock.overtime as u64);
+    hash.addBits(game.clock.over as u64);
+    hash.addBits(match game.clock.winner { None => 0, Some(LeftSide) => 1, Some(RightSide) => 2 });
+    hash.addFloat(game.serve.countdown);
+    hash.addBits(match game.serve.puckSide { LeftSide => 0, RightSide => 1 });
+    hash.addFloat(game.serve.puckSideTime);
+    hash.value
+}
+
+// Fires the puck from a position at something it hits within the first step. The puck is
+// so fast that a single step is longer than the puck plus a pole, so it only bounces when the
+// swept collision catches it. It must come back and never get closer than `clearance` to
+// `surface`, a point on whatever it was fired at.
+#[cfg(test)]
+fn firePuck(position: Vec2, direction: Vec2, surface: Vec2, clearance: f64) {
+    let mut game = setupGame(1);
+    game.physics.puckMaxSpeed = 12000.;
+    game.puck.teleport(position);
+    game.puck.velocity = direction * game.physics.puckMaxSpeed;
+    let inputs = Inputs { left: MoveTo(game.player.position), right: MoveTo(game.opponent.position) };
+    for uint::range(0, 3) |_| {
+        step(game, inputs);
+        assert!(game.puck.velocity.dot(direction) < 0.);
+        assert!((surface - game.puck.position).dot(direction) > clearance - 1e-6);
+    }
+}
+
+#[test]
+fn puckBouncesOffWalls() {
+    let radius = 30.;
+    firePuck(Vec2(320., 35.), Vec2(0., -1.), Vec2(320., 0.), radius);
+    firePuck(Vec2(320., 445.), Vec2(0., 1.), Vec2(320., 480.), radius);
+    firePuck(Vec2(35., 50.), Vec2(-1., 0.), Vec2(0., 50.), radius);
+    firePuck(Vec2(605., 50.), Vec2(1., 0.), Vec2(640., 50.), radius);
+}
+
+#[test]
+fn puckBouncesOffPoles() {
+    // Puck and pole radius
+    let clearance = 50.;
+    for [Vec2(0., 115.), Vec2(0., 365.)].each |&pole| {
+        firePuck(pole + Vec2(55., 0.), Vec2(-1., 0.), pole, clearance);
+    }
+    for [Vec2(640., 115.), Vec2(640., 365.)].each |&pole| {
+        firePuck(pole - Vec2(55., 0.), Vec2(1., 0.), pole, clearance);
+    }
+}
+
+#[test]
+fn noGoalsAfterTheMatch() {
+    let mut game = setupGame(1);
+    game.playerScore = 7;
+    let inputs = Inputs { left: MoveWith(Zero), right: MoveWith(Zero) };
+    step(game, inputs);
+    assert!(game.clock.over);
+    // Put the puck completely into the right goal
+    game.puck.teleport(Vec2(game.field.x + 45., game.field.y * 0.5));
+    step(game, inputs);
+    assert!(game.playerScore == 7 && game.events.is_empty());
+}
@@ -0,0 +1,61 @@
+// A goal: an opening in one of the side walls, with a pocket behind it. The puck can
+// rattle around in the pocket, but it counts as a goal as soon as it is completely
+// behind the goal line. Wall collisions and goal detection both use this geometry.
+
+use vec2::*;
+use game::*;
+use collider::*;
+
+pub struct Goal {
+    // Middle of the mouth, on the goal line
+    center: Vec2,
+    // Direction from the field into the pocket
+    inward: Vec2,
+    // Width of the mouth
+    width: f64,
+    // Distance from the goal line to the back wall of the pocket. Has to be larger
+    // than the diameter of the puck, otherwise the puck can never be completely in,
+    // plus the distance the puck covers in a step at full speed.
+    depth: f64,
+    // The side that defends this goal
+    side: Side
+}
+
+pub fn Goal(center: Vec2, inward: Vec2, width: f64, depth: f64, side: Side) -> Goal {
+    Goal { center: center, inward: inward, width: width, depth: depth, side: side }
+}
+
+pub impl Goal {
+    // Direction along the goal line
+    fn along(&self) -> Vec2 {
+        self.inward.normalRight()
+    }
+    // How far a position is behind the goal line
+    fn depthOf(&self, position: Vec2) -> f64 {
+        (position - self.center).dot(self.inward)
+    }
+    // Offset of a position along the goal line, from the middle of the mouth
+    fn lateral(&self, position: Vec2) -> f64 {
+        (position - self.center).dot(self.along())
+    }
+    fn inMouth(&self, position: Vec2) -> bool {
+        f64::abs(self.lateral(position)) < self.width * 0.5
+    }
+    // Whether a circle is completely behind the goal line
+    fn contains(&self, position: Vec2, radius: f64) -> bool {
+        self.inMouth(position) && self.depthOf(position) >= radius
+    }
+    // The two ends of the mouth
+    fn mouth(&self) -> (Vec2, Vec2) {
+        let half = self.along() * (self.width * 0.5);
+        (self.center - half, self.center + half)
+    }
+    // The two side walls and the back wall of the pocket
+    fn walls(&self) -> ~[Collider] {
+        let (first, second) = self.mouth();
+        let back = self.inward * self.depth;
+        ~[Segment(first, first + back),
+          Segment(second, second + back),
+          Segment(first + back, second + back)]
+    }
+}
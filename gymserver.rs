@@ -0,0 +1,112 @@
+extern mod std;
+extern mod airhockeysim;
+
+use std::json;
+use std::json::*;
+use airhockeysim::vec2::*;
+use airhockeysim::game::*;
+use airhockeysim::ai::*;
+use airhockeysim::env::*;
+use airhockeysim::options::*;
+
+// Serves the reinforcement learning environment over stdin and stdout, so that training
+// scripts can drive the game without a display. Every request is a JSON object on a
+// single line, and gets a single line in reply:
+//
+//     {"command": "reset", "seed": 1}
+//     {"command": "step", "action": [vx, vy]}
+//     {"command": "close"}
+//
+// Both reset and step reply with {"observation": [...], "reward": r, "done": false},
+// a request that can not be handled with {"error": "..."}.
+//
+// Usage: gymserver [--ai <level>] [--ai-style <style>] [--seconds <per episode>]
+
+fn reply(observation: &[f64], reward: f64, done: bool) -> ~str {
+    let values = observation.map(|&x| x.to_str());
+    fmt!("{\"observation\": [%s], \"reward\": %s, \"done\": %b}", str::connect(values, ", "), reward.to_str(), done)
+}
+
+fn errorReply(message: &str) -> ~str {
+    fmt!("{\"error\": %s}", json::to_str(&String(message.to_owned())))
+}
+
+fn getNumber(object: &Object, name: &str) -> Option<f64> {
+    match object.find(&name.to_owned()) {
+        Some(&Number(value)) => Some(value as f64),
+        _ => None
+    }
+}
+
+fn getAction(object: &Object) -> Option<Vec2> {
+    match object.find(&~"action") {
+        Some(&List(ref values)) if values.len() == 2 => match (&values[0], &values[1]) {
+            // Numbers like 1e999 are read as infinity, which would turn the paddle into NaN
+            (&Number(x), &Number(y)) if x - x == 0. && y - y == 0. => Some(Vec2(x as f64, y as f64)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// Handles a single request, returns None when the server should stop
+fn handleRequest(env: &mut Env, line: &str) -> Option<~str> {
+    let object = match json::from_str(line) {
+        Ok(Object(object)) => object,
+        Ok(_) => return Some(errorReply("expected an object")),
+        Err(e) => return Some(errorReply(fmt!("invalid json: %s", *e.msg)))
+    };
+    match object.find(&~"command") {
+        Some(&String(~"reset")) => {
+            let seed = getNumber(object, "seed").get_or_default(0.) as u64;
+            let observation = env.reset(seed);
+            Some(reply(observation, 0., false))
+        }
+        Some(&String(~"step")) => match getAction(object) {
+            Some(action) => {
+                let (observation, reward, done) = env.step(action);
+                Some(reply(observation, reward, done))
+            }
+            None => Some(errorReply("step needs an action [vx, vy] of two finite numbers"))
+        },
+        Some(&String(~"close")) => None,
+        _ => Some(errorReply("unknown command"))
+    }
+}
+
+fn main() {
+    let args = os::args();
+    let level = match getOption(args, "--ai") {
+        Some(name) => match aiLevelFromName(name) {
+            Some(level) => level,
+            None => { io::println(fmt!("Unknown ai %s, expected beginner, club, pro or unfair", name)); return; }
+        },
+        None => Club
+    };
+    let style = match getOption(args, "--ai-style") {
+        Some(name) => match aiStyleFromName(name) {
+            Some(style) => style,
+            None => { io::println(fmt!("Unknown ai style %s, expected classic or predictive", name)); return; }
+        },
+        None => Predictive
+    };
+    let seconds = match parseOption(args, "--seconds", 60., |s| f64::from_str(s)) {
+        Ok(seconds) => seconds,
+        Err(e) => { io::println(e); return; }
+    };
+
+    let mut env = Env(level, style, (seconds / TimeStep) as uint);
+    let input = io::stdin();
+    let output = io::stdout();
+    while !input.eof() {
+        let line = input.read_line();
+        if str::is_whitespace(line) { loop; }
+        match handleRequest(&mut env, line) {
+            Some(response) => {
+                output.write_line(response);
+                output.flush();
+            }
+            None => break
+        }
+    }
+}
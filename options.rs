@@ -0,0 +1,23 @@
+// Command line options like `--name value`, shared by the programs
+
+// Value of a command line option like `--name value`
+pub fn getOption(args: &[~str], name: &str) -> Option<~str> {
+    for uint::range(0, args.len()) |i| {
+        if args[i] == name.to_owned() && i + 1 < args.len() {
+            return Some(copy args[i + 1]);
+        }
+    }
+    None
+}
+
+// Value of an option converted with `parse`, or the default when the option is not given.
+// A value that can not be converted is an error, with a message for the user.
+pub fn parseOption<T>(args: &[~str], name: &str, default: T, parse: &fn(&str) -> Option<T>) -> Result<T, ~str> {
+    match getOption(args, name) {
+        Some(value) => match parse(value) {
+            Some(x) => Ok(x),
+            None => Err(fmt!("Invalid value %s for %s", value, name))
+        },
+        None => Ok(default)
+    }
+}
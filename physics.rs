@@ -0,0 +1,198 @@
+use vec2::*;
+use strictmath;
+
+// Tuning of the simulation. Responses are time constants in seconds: the time it takes
+// to cover about 63% (1 - 1/e) of the difference between the current and the desired value.
+pub struct PhysicsParams {
+    // Time for the puck to lose 63% of its speed
+    puckDamping: f64,
+    puckSpinDamping: f64,
+    // In units per second
+    puckMaxSpeed: f64,
+    // Time for a paddle to pick up the velocity it needs to reach the target of its controller
+    targetResponse: f64,
+    // Time for the computer controlled paddles to pick up the velocity they want
+    aiResponse: f64,
+    // Time in which a paddle tries to close the distance to its target
+    paddleArrival: f64,
+    // In units per second
+    paddleMaxSpeed: f64
+}
+pub fn defaultPhysicsParams() -> PhysicsParams {
+    PhysicsParams {
+        puckDamping: 1.66,
+        puckSpinDamping: 1.66,
+        puckMaxSpeed: 1800.,
+        targetResponse: 0.014,
+        aiResponse: 0.075,
+        paddleArrival: 0.05,
+        paddleMaxSpeed: 3000.
+    }
+}
+
+// Factor by which a value decays in dt seconds, given its time constant
+pub fn decay(dt:f64, timeConstant:f64) -> f64 {
+    strictmath::exp(-dt / timeConstant)
+}
+
+// Moves a velocity towards a target velocity with exponential smoothing
+pub fn approach(velocity:Vec2, target:Vec2, dt:f64, timeConstant:f64) -> Vec2 {
+    target + (velocity - target) * decay(dt, timeConstant)
+}
+
+pub struct PhysicalCircle {
+    position: Vec2,
+    // Position at the start of the last simulation step, used for render interpolation
+    previousPosition: Vec2,
+    // In units per second
+    velocity: Vec2,
+    // In radians, with the previous angle kept for render interpolation like previousPosition
+    angle: f64,
+    previousAngle: f64,
+    // In radians per second
+    angularVelocity: f64,
+    radius: f64,
+    // f64::infinity for bodies that can not be pushed, like the poles
+    mass: f64,
+    // Fraction of the approaching speed that is kept after a bounce
+    restitution: f64,
+    // Coulomb friction coefficient along the contact surface
+    friction: f64
+}
+pub impl PhysicalCircle {
+    fn interpolatedPosition(&self, alpha: f64) -> Vec2 {
+        self.previousPosition + (self.position - self.previousPosition) * alpha
+    }
+    fn interpolatedAngle(&self, alpha: f64) -> f64 {
+        self.previousAngle + (self.angle - self.previousAngle) * alpha
+    }
+    fn teleport(&mut self, position: Vec2) {
+        self.position = position;
+        self.previousPosition = position;
+        self.velocity = Zero;
+        self.previousAngle = self.angle;
+        self.angularVelocity = 0.;
+    }
+    fn inverseMass(&self) -> f64 {
+        1. / self.mass
+    }
+    // Treats the circle as a solid disc
+    fn inverseInertia(&self) -> f64 {
+        2. / (self.mass * self.radius * self.radius)
+    }
+    // Velocity of the point at the given offset from the center, including spin
+    fn pointVelocity(&self, offset: Vec2) -> Vec2 {
+        self.velocity + offset.normalRight() * self.angularVelocity
+    }
+    fn applyImpulse(&mut self, impulse: Vec2, offset: Vec2) {
+        self.velocity += impulse * self.inverseMass();
+        self.angularVelocity += offset.cross(impulse) * self.inverseInertia();
+    }
+}
+pub fn newCircle(position:Vec2, radius:f64, mass:f64, restitution:f64, friction:f64) -> PhysicalCircle {
+    PhysicalCircle {
+        position: position,
+        previousPosition: position,
+        velocity: Zero,
+        angle: 0.,
+        previousAngle: 0.,
+        angularVelocity: 0.,
+        radius: radius,
+        mass: mass,
+        restitution: restitution,
+        friction: friction
+    }
+}
+
+// Earliest fraction of the step at which two circles moving linearly (a0 to a1 and b0 to b1) touch
+pub fn sweptCircleTime(a0:Vec2, a1:Vec2, b0:Vec2, b1:Vec2, radius:f64) -> Option<f64> {
+    let start = a0 - b0;
+    let motion = (a1 - a0) - (b1 - b0);
+    let a = motion.length2();
+    let b = 2. * start.dot(motion);
+    let c = start.length2() - radius * radius;
+    // Already overlapping: only report it when the circles are still approaching
+    if c < 0. { return if b < 0. { Some(0.) } else { None }; }
+    if a == 0. { return None; }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. { return None; }
+    let t = (-b - f64::sqrt(discriminant)) / (2. * a);
+    if t >= 0. && t <= 1. { Some(t) } else { None }
+}
+
+// Earliest fraction of the step at which a circle moving from a to b reaches a wall.
+// The wall consists of all points p where p.dot(normal) == offset.
+pub fn sweptWallTime(a:Vec2, b:Vec2, radius:f64, normal:Vec2, offset:f64) -> Option<f64> {
+    let da = a.dot(normal) - offset - radius;
+    let db = b.dot(normal) - offset - radius;
+    if da >= 0. && db < 0. { Some(da / (da - db)) } else { None }
+}
+
+// Offsets that move two overlapping circles apart along their contact normal.
+// The correction is divided by inverse mass, so a body with infinite mass does not move.
+pub fn getSeparation(a:&PhysicalCircle, b:&PhysicalCircle) -> Option<(Vec2, Vec2)> {
+    let diff = a.position - b.position;
+    let depth = a.radius + b.radius - diff.length();
+    let aInverseMass = a.inverseMass();
+    let bInverseMass = b.inverseMass();
+    let totalInverseMass = aInverseMass + bInverseMass;
+    if depth <= 0. || totalInverseMass == 0. { return None; }
+    // Circles at the exact same position still need to be pushed apart in some direction
+    let normal = diff.normalizeOr(XAxis);
+    Some((normal * (depth * aInverseMass / totalInverseMass),
+          -normal * (depth * bInverseMass / totalInverseMass)))
+}
+
+// Impulse on the first body of a contact, where normal points towards the first body and
+// relativeVelocity is the velocity of the contact point of the first body relative to the second.
+// The second body receives the same impulse in the opposite direction.
+// Sliding is resisted with tangentInverseMassSum, which includes the rotational inertia of both bodies.
+pub fn getCollisionImpulse(normal:Vec2, relativeVelocity:Vec2, inverseMassSum:f64, tangentInverseMassSum:f64,
+                       restitution:f64, friction:f64) -> Vec2 {
+    let impact = normal.dot(relativeVelocity);
+    if (impact >= 0. || inverseMassSum == 0.) { return Zero; }
+    let normalImpulse = -(1. + restitution) * impact / inverseMassSum;
+
+    // Friction works against sliding along the surface, but can not exceed friction * normalImpulse
+    let tangent = (relativeVelocity - normal * impact).normalizeOrZero();
+    let stopImpulse = tangent.dot(relativeVelocity) / tangentInverseMassSum;
+    let maxFriction = friction * normalImpulse;
+    let tangentImpulse = if stopImpulse > maxFriction { maxFriction } else { stopImpulse };
+
+    normal * normalImpulse - tangent * tangentImpulse
+}
+
+// Inverse mass felt along the contact surface by a circle that can spin
+pub fn tangentInverseMass(a:&PhysicalCircle) -> f64 {
+    a.inverseMass() + a.radius * a.radius * a.inverseInertia()
+}
+
+pub fn collide(a:&mut PhysicalCircle, b:&mut PhysicalCircle, normal:Vec2) {
+    let aOffset = -normal * a.radius;
+    let bOffset = normal * b.radius;
+    let impulse = getCollisionImpulse(normal, a.pointVelocity(aOffset) - b.pointVelocity(bOffset),
+        a.inverseMass() + b.inverseMass(),
+        tangentInverseMass(a) + tangentInverseMass(b),
+        a.restitution * b.restitution,
+        f64::sqrt(a.friction * b.friction));
+    a.applyImpulse(impulse, aOffset);
+    b.applyImpulse(-impulse, bOffset);
+}
+
+// Collision against an immovable surface
+pub fn collideWall(a:&mut PhysicalCircle, normal:Vec2, restitution:f64, friction:f64) {
+    let offset = -normal * a.radius;
+    let impulse = getCollisionImpulse(normal, a.pointVelocity(offset),
+        a.inverseMass(),
+        tangentInverseMass(a),
+        a.restitution * restitution,
+        f64::sqrt(a.friction * friction));
+    a.applyImpulse(impulse, offset);
+}
+
+// Clamps a position to [min, max] and stops any velocity that points further out
+pub fn clampAxis(position:f64, velocity:f64, min:f64, max:f64) -> (f64, f64) {
+    if position < min { (min, if velocity < 0. { 0. } else { velocity }) }
+    else if position > max { (max, if velocity > 0. { 0. } else { velocity }) }
+    else { (position, velocity) }
+}
@@ -1,3 +1,7 @@
 #[pkg(id = "org.frozencow.airhockey", vers = "0.0.5")];
 #[pkg_dep(url = "git://github.com/brson/rust-sdl.git")];
-#[pkg_crate(file = "airhockey.rc")];
\ No newline at end of file
+#[pkg_crate(file = "airhockeysim.rc")];
+#[pkg_crate(file = "airhockey.rc")];
+#[pkg_crate(file = "simulate.rs")];
+#[pkg_crate(file = "tournament.rs")];
+#[pkg_crate(file = "gymserver.rs")];
\ No newline at end of file
@@ -0,0 +1,190 @@
+// Opponents driven by a trained feed-forward neural network.
+//
+// Networks are stored as text:
+//
+//     airhockey-policy 1
+//     layer 14 32 relu
+//     <weight> ... <weight> <bias>
+//     ...
+//     layer 32 2 linear
+//     ...
+//
+// Every layer starts with a line giving its number of inputs and outputs and its activation
+// (linear, relu or tanh), followed by one line for each output with a weight for every input
+// and finally the bias. The first layer takes the observation of env.rs, the last layer gives
+// the velocity of the paddle, in the same coordinates as the actions of the environment.
+
+use vec2::*;
+use strictmath;
+use game::*;
+use env::*;
+use controller::*;
+
+pub static PolicyVersion: uint = 1;
+
+pub enum Activation { Linear, Relu, Tanh }
+
+fn activationFromName(name: &str) -> Option<Activation> {
+    match name {
+        "linear" => Some(Linear),
+        "relu" => Some(Relu),
+        "tanh" => Some(Tanh),
+        _ => None
+    }
+}
+
+fn activate(activation: Activation, x: f64) -> f64 {
+    match activation {
+        Linear => x,
+        Relu => if x > 0. { x } else { 0. },
+        // Written in terms of exp, so that inference stays deterministic. Beyond 20
+        // the result is 1 or -1 within the precision of an f64.
+        Tanh if x > 20. => 1.,
+        Tanh if x < -20. => -1.,
+        Tanh => 1. - 2. / (strictmath::exp(2. * x) + 1.)
+    }
+}
+
+pub struct Layer {
+    inputs: uint,
+    outputs: uint,
+    // One row of `inputs` weights for every output
+    weights: ~[f64],
+    biases: ~[f64],
+    activation: Activation
+}
+
+pub impl Layer {
+    fn evaluate(&self, input: &[f64]) -> ~[f64] {
+        do vec::from_fn(self.outputs) |o| {
+            let mut sum = self.biases[o];
+            for uint::range(0, self.inputs) |i| {
+                sum += self.weights[o * self.inputs + i] * input[i];
+            }
+            activate(self.activation, sum)
+        }
+    }
+}
+
+pub struct Network {
+    layers: ~[Layer]
+}
+
+pub impl Network {
+    fn evaluate(&self, input: &[f64]) -> ~[f64] {
+        let mut values = vec::from_slice(input);
+        for self.layers.each |layer| {
+            values = layer.evaluate(values);
+        }
+        values
+    }
+}
+
+pub fn readNetwork(path: &Path) -> Result<Network, ~str> {
+    let contents = match io::read_whole_file_str(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(e)
+    };
+    let lines = str::lines(contents);
+    if lines.len() < 1 {
+        return Err(~"policy is empty");
+    }
+
+    let header = str::words(lines[0]);
+    if header.len() != 2 || header[0] != ~"airhockey-policy" {
+        return Err(~"not a policy file");
+    }
+    match uint::from_str(header[1]) {
+        Some(version) if version == PolicyVersion => {}
+        Some(version) => return Err(fmt!("unsupported policy version %u", version)),
+        None => return Err(~"invalid policy version")
+    }
+
+    let mut layers = ~[];
+    let mut line = 1;
+    while line < lines.len() {
+        if str::is_whitespace(lines[line]) { line += 1; loop; }
+
+        let layerLine = str::words(lines[line]);
+        if layerLine.len() != 4 || layerLine[0] != ~"layer" {
+            return Err(fmt!("line %u: expected layer", line + 1));
+        }
+        let (inputs, outputs) = match (uint::from_str(layerLine[1]), uint::from_str(layerLine[2])) {
+            (Some(inputs), Some(outputs)) if inputs > 0 && outputs > 0 => (inputs, outputs),
+            _ => return Err(fmt!("line %u: invalid layer size", line + 1))
+        };
+        let activation = match activationFromName(layerLine[3]) {
+            Some(activation) => activation,
+            None => return Err(fmt!("line %u: unknown activation %s", line + 1, layerLine[3]))
+        };
+        let expectedInputs = if layers.is_empty() { ObservationSize } else { layers[layers.len() - 1].outputs };
+        if inputs != expectedInputs {
+            return Err(fmt!("line %u: layer has %u inputs, expected %u", line + 1, inputs, expectedInputs));
+        }
+        line += 1;
+
+        let mut weights = vec::with_capacity(inputs * outputs);
+        let mut biases = vec::with_capacity(outputs);
+        for uint::range(0, outputs) |_| {
+            if line >= lines.len() {
+                return Err(~"policy is truncated");
+            }
+            let values = str::words(lines[line]);
+            if values.len() != inputs + 1 {
+                return Err(fmt!("line %u: expected %u weights and a bias", line + 1, inputs));
+            }
+            for values.eachi |i, value| {
+                match f64::from_str(*value) {
+                    // Infinite weights and NaN would only ever produce garbage
+                    Some(x) if x - x != 0. => return Err(fmt!("line %u: %s is not a finite number", line + 1, *value)),
+                    Some(x) if i < inputs => weights.push(x),
+                    Some(x) => biases.push(x),
+                    None => return Err(fmt!("line %u: invalid number %s", line + 1, *value))
+                }
+            }
+            line += 1;
+        }
+        layers.push(Layer { inputs: inputs, outputs: outputs, weights: weights, biases: biases, activation: activation });
+    }
+
+    if layers.is_empty() {
+        return Err(~"policy has no layers");
+    }
+    if layers[layers.len() - 1].outputs != 2 {
+        return Err(~"the last layer must have two outputs");
+    }
+    Ok(Network { layers: layers })
+}
+
+// Plays with a network. Like a human it only reacts every so often,
+// in between it keeps moving the way it decided last.
+pub struct PolicyController {
+    network: Network,
+    // Steps between two decisions
+    interval: uint,
+    // Steps until the next decision
+    countdown: uint,
+    velocity: Vec2
+}
+
+// Takes `rate` decisions per second
+pub fn PolicyController(network: Network, rate: f64) -> PolicyController {
+    let interval = f64::max(1., f64::round(1. / (rate * TimeStep))) as uint;
+    PolicyController { network: network, interval: interval, countdown: 0, velocity: Zero }
+}
+
+impl Controller for PolicyController {
+    fn control(&mut self, game: &Game, side: Side, _: &Devices) -> Command {
+        if self.countdown == 0 {
+            let output = self.network.evaluate(observation(game, side));
+            // The network sees the table from the left, mirror its answer back
+            self.velocity = match side {
+                LeftSide => Vec2(output[0], output[1]),
+                RightSide => Vec2(-output[0], output[1])
+            };
+            self.countdown = self.interval;
+        }
+        self.countdown -= 1;
+        MoveWith(self.velocity)
+    }
+}
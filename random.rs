@@ -0,0 +1,28 @@
+// A small pseudo random number generator (xorshift64*) with a state that can be
+// seeded, copied and hashed, so that simulations can be replayed exactly.
+
+pub struct Rng {
+    state: u64
+}
+
+pub fn Rng(seed: u64) -> Rng {
+    // A state of zero would only ever produce zeroes
+    Rng { state: if seed == 0 { 0x9E3779B97F4A7C15u64 } else { seed } }
+}
+
+pub impl Rng {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state * 0x2545F4914F6CDD1Du64
+    }
+    // Uniformly distributed in [0, 1)
+    fn nextFloat(&mut self) -> f64 {
+        ((self.next() >> 11) as f64) / 9007199254740992.
+    }
+    // Uniformly distributed in [min, max)
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.nextFloat()
+    }
+}
@@ -0,0 +1,215 @@
+// Recording of a match, which can be played back exactly because the simulation is deterministic.
+//
+// Replays are stored as text:
+//
+//     airhockey-replay 11
+//     seed 1234
+//     half-field true
+//     rules <goals to win> <periods> <period length> <win by two> <overtime> <serve countdown> <stuck time>
+//     table <number of lines>
+//     <the lines of the table, see table.rs>
+//     <left command> <right command>
+//     serve-reset
+//     ...
+//
+// with one line for each step, containing the commands of both paddles in that step. A command
+// is `to <x> <y>` or `with <x> <y>`, for MoveTo and MoveWith. Coordinates are stored as the
+// hexadecimal bits of the f64, so that they are read back exactly. A serve-reset line
+// means the puck was put back at its spawn point before the step on the next line.
+// Since the commands of both sides are recorded, it does not matter what controlled the paddles.
+// Older versions did not store the table, handled the walls differently or started without
+// a face-off, they can no longer be played.
+
+use vec2::*;
+use game::*;
+use rules::*;
+use table::*;
+
+pub static ReplayVersion: uint = 11;
+
+pub struct Replay {
+    seed: u64,
+    halfFieldRule: bool,
+    rules: MatchRules,
+    table: Table,
+    inputs: ~[Inputs],
+    // Steps before which the puck was put back at its spawn point, in order
+    serveResets: ~[uint]
+}
+
+pub fn Replay(seed: u64, halfFieldRule: bool, rules: MatchRules, table: Table) -> Replay {
+    Replay { seed: seed, halfFieldRule: halfFieldRule, rules: rules, table: table, inputs: ~[], serveResets: ~[] }
+}
+
+pub impl Replay {
+    fn record(&mut self, inputs: Inputs) {
+        self.inputs.push(inputs);
+    }
+    // Records that the puck is put back at its spawn point before the next step
+    fn recordServeReset(&mut self) {
+        self.serveResets.push(self.inputs.len());
+    }
+    // Plays the recorded step with the given index
+    fn stepGame(&self, game: &mut Game, index: uint) {
+        if vec::contains(self.serveResets, &index) {
+            resetServe(game);
+        }
+        step(game, self.inputs[index]);
+    }
+    fn len(&self) -> uint {
+        self.inputs.len()
+    }
+    // The game as it was before the first step
+    fn newGame(&self) -> ~Game {
+        let mut game = setupGameOnTable(self.seed, &self.table);
+        game.halfFieldRule = self.halfFieldRule;
+        setRules(game, self.rules);
+        game
+    }
+    // The game after the given number of steps
+    fn gameAt(&self, steps: uint) -> ~Game {
+        let mut game = self.newGame();
+        for uint::range(0, uint::min(steps, self.len())) |i| {
+            self.stepGame(game, i);
+        }
+        game
+    }
+}
+
+fn floatToHex(x: f64) -> ~str {
+    u64::to_str_radix(unsafe { cast::transmute::<f64, u64>(x) }, 16)
+}
+
+fn hexToFloat(s: &str) -> Option<f64> {
+    u64::from_str_radix(s, 16).map(|&bits| unsafe { cast::transmute::<u64, f64>(bits) })
+}
+
+fn commandToStr(command: Command) -> ~str {
+    match command {
+        MoveTo(target) => fmt!("to %s %s", floatToHex(target.x), floatToHex(target.y)),
+        MoveWith(velocity) => fmt!("with %s %s", floatToHex(velocity.x), floatToHex(velocity.y))
+    }
+}
+
+// Parses the three words of a command
+fn commandFromWords(words: &[~str]) -> Option<Command> {
+    let vector = match (hexToFloat(words[1]), hexToFloat(words[2])) {
+        (Some(x), Some(y)) => Vec2(x, y),
+        _ => return None
+    };
+    match words[0] {
+        ~"to" => Some(MoveTo(vector)),
+        ~"with" => Some(MoveWith(vector)),
+        _ => None
+    }
+}
+
+pub fn writeReplay(path: &Path, replay: &Replay) -> Result<(), ~str> {
+    match io::file_writer(path, [io::Create, io::Truncate]) {
+        Ok(writer) => {
+            writer.write_line(fmt!("airhockey-replay %u", ReplayVersion));
+            writer.write_line(fmt!("seed %s", replay.seed.to_str()));
+            writer.write_line(fmt!("half-field %b", replay.halfFieldRule));
+            let rules = replay.rules;
+            writer.write_line(fmt!("rules %u %u %s %b %b %s %s", rules.goalsToWin, rules.periods,
+                floatToHex(rules.periodLength), rules.winByTwo, rules.overtime,
+                floatToHex(rules.serveCountdown), floatToHex(rules.stuckTime)));
+            writer.write_line(fmt!("table %u", replay.table.source.len()));
+            for replay.table.source.each |line| {
+                writer.write_line(*line);
+            }
+            for replay.inputs.eachi |i, inputs| {
+                if vec::contains(replay.serveResets, &i) {
+                    writer.write_line("serve-reset");
+                }
+                writer.write_line(fmt!("%s %s", commandToStr(inputs.left), commandToStr(inputs.right)));
+            }
+            Ok(())
+        }
+        Err(e) => Err(e)
+    }
+}
+
+pub fn readReplay(path: &Path) -> Result<Replay, ~str> {
+    let contents = match io::read_whole_file_str(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(e)
+    };
+    let lines = str::lines(contents);
+    if lines.len() < 3 {
+        return Err(~"replay is truncated");
+    }
+
+    let header = str::words(lines[0]);
+    if header.len() != 2 || header[0] != ~"airhockey-replay" {
+        return Err(~"not a replay file");
+    }
+    match uint::from_str(header[1]) {
+        Some(version) if version == ReplayVersion => {}
+        Some(version) => return Err(fmt!("unsupported replay version %u", version)),
+        None => return Err(~"invalid replay version")
+    }
+
+    let seedLine = str::words(lines[1]);
+    if seedLine.len() != 2 || seedLine[0] != ~"seed" {
+        return Err(~"line 2: expected seed");
+    }
+    let seed = match u64::from_str(seedLine[1]) {
+        Some(seed) => seed,
+        None => return Err(~"line 2: invalid seed")
+    };
+
+    let halfFieldLine = str::words(lines[2]);
+    if halfFieldLine.len() != 2 || halfFieldLine[0] != ~"half-field" {
+        return Err(~"line 3: expected half-field");
+    }
+    let halfFieldRule = match bool::from_str(halfFieldLine[1]) {
+        Some(value) => value,
+        None => return Err(~"line 3: expected true or false")
+    };
+
+    let rulesLine = if lines.len() > 3 { str::words(lines[3]) } else { ~[] };
+    if rulesLine.len() != 8 || rulesLine[0] != ~"rules" {
+        return Err(~"line 4: expected rules");
+    }
+    let rules = match (uint::from_str(rulesLine[1]), uint::from_str(rulesLine[2]), hexToFloat(rulesLine[3]),
+                       bool::from_str(rulesLine[4]), bool::from_str(rulesLine[5]),
+                       hexToFloat(rulesLine[6]), hexToFloat(rulesLine[7])) {
+        (Some(goalsToWin), Some(periods), Some(periodLength), Some(winByTwo), Some(overtime),
+         Some(serveCountdown), Some(stuckTime)) =>
+            MatchRules { goalsToWin: goalsToWin, periods: periods, periodLength: periodLength,
+                         winByTwo: winByTwo, overtime: overtime,
+                         serveCountdown: serveCountdown, stuckTime: stuckTime },
+        _ => return Err(~"line 4: invalid rules")
+    };
+
+    let tableLine = if lines.len() > 4 { str::words(lines[4]) } else { ~[] };
+    if tableLine.len() != 2 || tableLine[0] != ~"table" {
+        return Err(~"line 5: expected table");
+    }
+    let tableEnd = match uint::from_str(tableLine[1]) {
+        Some(count) if 5 + count <= lines.len() => 5 + count,
+        _ => return Err(~"line 5: invalid table length")
+    };
+    let table = match parseTable(lines.slice(5, tableEnd)) {
+        Ok(table) => table,
+        Err(errors) => return Err(fmt!("invalid table: %s", str::connect(errors, ", ")))
+    };
+
+    let mut replay = Replay(seed, halfFieldRule, rules, table);
+    for uint::range(tableEnd, lines.len()) |i| {
+        let words = str::words(lines[i]);
+        if words.len() == 1 && words[0] == ~"serve-reset" {
+            replay.recordServeReset();
+            loop;
+        }
+        if words.len() != 6 {
+            return Err(fmt!("line %u: expected two commands", i + 1));
+        }
+        match (commandFromWords(words.slice(0, 3)), commandFromWords(words.slice(3, 6))) {
+            (Some(left), Some(right)) => replay.record(Inputs { left: left, right: right }),
+            _ => return Err(fmt!("line %u: invalid command", i + 1))
+        }
+    }
+    Ok(replay)
+}
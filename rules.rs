@@ -0,0 +1,157 @@
+// How a match is won: by reaching a number of goals, by leading when the time is up,
+// or in overtime when the regular time ends in a tie. Also how play restarts after a goal.
+
+use vec2::*;
+use game::*;
+
+pub struct MatchRules {
+    // Goals needed to win, zero for no limit
+    goalsToWin: uint,
+    // Number of timed periods, zero for a match without a clock
+    periods: uint,
+    // Seconds in each period
+    periodLength: f64,
+    // The winner has to lead by at least two goals
+    winByTwo: bool,
+    // A match that is tied when the time is up goes to overtime, where the next goal wins
+    // (or the first lead of two goals, with winByTwo). Without overtime it ends in a draw.
+    overtime: bool,
+    // Seconds after a restart during which both paddles have to stay on their own half
+    serveCountdown: f64,
+    // A puck that stays on one half for this many seconds is a foul, and the other side
+    // gets to serve. Zero to allow the puck to stay anywhere.
+    stuckTime: f64
+}
+
+// First to seven, with a seven second limit for keeping the puck on one half
+pub fn defaultMatchRules() -> MatchRules {
+    MatchRules { goalsToWin: 7, periods: 0, periodLength: 0., winByTwo: false, overtime: true,
+                 serveCountdown: 1.5, stuckTime: 7. }
+}
+
+pub struct MatchClock {
+    // Index of the current period
+    period: uint,
+    // Seconds left in the current period
+    remaining: f64,
+    overtime: bool,
+    over: bool,
+    // None when the match ended in a draw
+    winner: Option<Side>
+}
+
+pub fn MatchClock(rules: &MatchRules) -> MatchClock {
+    MatchClock { period: 0, remaining: rules.periodLength, overtime: false, over: false, winner: None }
+}
+
+pub struct ServeState {
+    // Seconds until the paddles may cross the centre line again
+    countdown: f64,
+    // The half of the table the puck is on, and for how many seconds it has been there
+    puckSide: Side,
+    puckSideTime: f64
+}
+
+// The face-off at the start of a match
+pub fn ServeState(rules: &MatchRules) -> ServeState {
+    ServeState { countdown: rules.serveCountdown, puckSide: LeftSide, puckSideTime: 0. }
+}
+
+fn halfOf(game: &Game, position: Vec2) -> Side {
+    if position.x < game.field.x * 0.5 { LeftSide } else { RightSide }
+}
+
+// How far a serve can be from the middle of the half, up or down
+pub static ServeSpread: f64 = 40.;
+
+// Where a side serves from: the middle of its half, moved up or down by an offset.
+// parseTable checks that the puck fits anywhere within ServeSpread of the middle.
+pub fn serveSpot(field: Vec2, side: Side, offset: f64) -> Vec2 {
+    let x = match side {
+        LeftSide => field.x * 0.25,
+        RightSide => field.x * 0.75
+    };
+    Vec2(x, field.y * 0.5 + offset)
+}
+
+// Gives the puck to the given side: it is put at rest in the middle of that side's half,
+// moved up or down by a random amount, and the countdown starts
+pub fn awardServe(game: &mut Game, side: Side) {
+    let offset = game.rng.range(-1., 1.) * ServeSpread;
+    game.puck.teleport(serveSpot(game.field, side, offset));
+    game.serve = ServeState { countdown: game.rules.serveCountdown, puckSide: side, puckSideTime: 0. };
+}
+
+// Runs the countdown and calls a foul on a side that keeps the puck on its half for too long
+pub fn handleServe(game: &mut Game, dt: f64) {
+    if game.clock.over { return; }
+    game.serve.countdown = f64::max(0., game.serve.countdown - dt);
+
+    let side = halfOf(game, game.puck.position);
+    if side != game.serve.puckSide {
+        game.serve.puckSide = side;
+        game.serve.puckSideTime = 0.;
+    }
+    game.serve.puckSideTime += dt;
+    if game.rules.stuckTime > 0. && game.serve.puckSideTime >= game.rules.stuckTime {
+        game.events.push(Foul(side));
+        awardServe(game, opposite(side));
+    }
+}
+
+// The side that is ahead by enough goals to win, if any
+fn decidingLead(game: &Game) -> Option<Side> {
+    let margin = if game.rules.winByTwo { 2 } else { 1 };
+    if game.playerScore >= game.opponentScore + margin { Some(LeftSide) }
+    else if game.opponentScore >= game.playerScore + margin { Some(RightSide) }
+    else { None }
+}
+
+fn endMatch(game: &mut Game, winner: Option<Side>) {
+    game.clock.over = true;
+    game.clock.winner = winner;
+    game.events.push(MatchEnded(winner));
+}
+
+// Advances the clock and decides whether the match is over. Called after the goals of a step.
+pub fn handleRules(game: &mut Game, dt: f64) {
+    if game.clock.over { return; }
+    let rules = game.rules;
+
+    if game.clock.overtime {
+        let lead = decidingLead(game);
+        if lead.is_some() { endMatch(game, lead); }
+        return;
+    }
+
+    if rules.goalsToWin > 0 && uint::max(game.playerScore, game.opponentScore) >= rules.goalsToWin {
+        let lead = decidingLead(game);
+        if lead.is_some() { endMatch(game, lead); return; }
+    }
+
+    if rules.periods == 0 { return; }
+    game.clock.remaining -= dt;
+    if game.clock.remaining > 0. { return; }
+
+    if game.clock.period + 1 < rules.periods {
+        game.clock.period += 1;
+        game.clock.remaining = rules.periodLength;
+        game.events.push(PeriodEnded);
+        return;
+    }
+
+    // The regular time is up
+    game.clock.remaining = 0.;
+    let lead = decidingLead(game);
+    if lead.is_some() {
+        endMatch(game, lead);
+    } else if rules.overtime {
+        game.clock.overtime = true;
+        game.events.push(PeriodEnded);
+    } else if game.playerScore == game.opponentScore {
+        endMatch(game, None);
+    } else {
+        // Ahead by a single goal while two were needed, without overtime to settle it
+        endMatch(game, Some(if game.playerScore > game.opponentScore { LeftSide } else { RightSide }));
+    }
+}
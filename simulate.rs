@@ -0,0 +1,72 @@
+extern mod airhockeysim;
+
+use airhockeysim::game::*;
+use airhockeysim::controller::*;
+use airhockeysim::ai::*;
+use airhockeysim::options::*;
+
+// The player paddle simply chases the puck
+struct ChaseController;
+
+impl Controller for ChaseController {
+    fn control(&mut self, game: &Game, _: Side, _: &Devices) -> Command {
+        MoveTo(game.puck.position)
+    }
+}
+
+// The chasing player against the club opponent
+fn controllers(seed: u64) -> (~Controller, ~Controller) {
+    (~ChaseController as ~Controller, ~AiController(Club, Classic, seed) as ~Controller)
+}
+
+// Plays matches without any graphics.
+// Usage: simulate [--matches <n>] [--seconds <seconds per match>]
+//        simulate --hash <steps> [--seed <seed>]
+fn main() {
+    let args = os::args();
+    if getOption(args, "--hash").is_some() {
+        // Print the hash of the game state after a number of steps. Every build on every
+        // platform has to print the same value, otherwise replays would go out of sync.
+        let steps = match parseOption(args, "--hash", 0, |s| uint::from_str(s)) {
+            Ok(steps) => steps,
+            Err(e) => { io::println(e); return; }
+        };
+        let seed = match parseOption(args, "--seed", 1, |s| u64::from_str(s)) {
+            Ok(seed) => seed,
+            Err(e) => { io::println(e); return; }
+        };
+        let mut game = setupGame(seed);
+        let (mut left, mut right) = controllers(seed);
+        let devices = Devices();
+        for uint::range(0, steps) |_| {
+            stepControllers(game, &mut left, &mut right, &devices);
+        }
+        io::println(hashGame(game).to_str());
+        return;
+    }
+
+    let matches = match parseOption(args, "--matches", 100, |s| uint::from_str(s)) {
+        Ok(matches) => matches,
+        Err(e) => { io::println(e); return; }
+    };
+    let seconds = match parseOption(args, "--seconds", 60., |s| f64::from_str(s)) {
+        Ok(seconds) => seconds,
+        Err(e) => { io::println(e); return; }
+    };
+    let steps = (seconds / TimeStep) as uint;
+
+    let mut playerGoals = 0;
+    let mut opponentGoals = 0;
+    for uint::range(0, matches) |index| {
+        let mut game = setupGame(index as u64);
+        let (mut left, mut right) = controllers(index as u64);
+        let devices = Devices();
+        for uint::range(0, steps) |_| {
+            stepControllers(game, &mut left, &mut right, &devices);
+        }
+        playerGoals += game.playerScore;
+        opponentGoals += game.opponentScore;
+    }
+
+    io::println(fmt!("%u matches: player scored %u, opponent scored %u", matches, playerGoals, opponentGoals));
+}
@@ -0,0 +1,39 @@
+// Math functions that give bit-identical results on every platform.
+// They only use addition, subtraction, multiplication and division, which IEEE 754
+// specifies exactly, unlike the functions of libm that differ between implementations.
+// This needs a target that does f64 arithmetic in 64 bits (like x86 with SSE2).
+
+static Ln2: f64 = 0.6931471805599453;
+// Above this exp overflows to infinity, below the other bound it underflows to zero
+static MaxExpArgument: f64 = 709.782712893384;
+static MinExpArgument: f64 = -745.1332191019412;
+
+// 2^n for -1022 <= n <= 1023, built from the exponent bits
+fn powerOfTwo(n: int) -> f64 {
+    unsafe { cast::transmute::<u64, f64>(((n + 1023) as u64) << 52) }
+}
+
+pub fn exp(x: f64) -> f64 {
+    if x != x { return x; }
+    if x > MaxExpArgument { return f64::infinity; }
+    if x < MinExpArgument { return 0.; }
+
+    // Split x into k*ln(2) + r with |r| <= ln(2)/2, so that exp(x) = 2^k * exp(r)
+    let k = f64::floor(x / Ln2 + 0.5);
+    let r = x - k * Ln2;
+
+    // Taylor series of exp(r), which converges quickly for small r
+    let mut term = 1.;
+    let mut sum = 1.;
+    for uint::range(1, 18) |n| {
+        term = term * r / (n as f64);
+        sum += term;
+    }
+
+    // Multiplying by a power of two is exact unless the result is subnormal. At the ends of
+    // the range 2^k itself does not fit in an f64, so it is applied in two steps.
+    let k = k as int;
+    if k > 1023 { sum * powerOfTwo(k - 1023) * powerOfTwo(1023) }
+    else if k < -1022 { sum * powerOfTwo(k + 1022) * powerOfTwo(-1022) }
+    else { sum * powerOfTwo(k) }
+}
@@ -0,0 +1,433 @@
+// Layout of a table: the size of the field, the goals, fixed obstacles like the poles next
+// to the goals, the puck and the paddles, and where they start.
+//
+// Tables are stored as text:
+//
+//     airhockey-table 1
+//     field <width> <height>
+//     goal <left|right> <middle> <width> <depth>
+//     pole <x> <y> <radius>
+//     obstacle <x> <y> <radius> <restitution>
+//     puck <radius> <mass> <restitution> <friction>
+//     paddle <radius> <mass> <restitution> <friction>
+//     spawn <left|right|puck> <x> <y>
+//     corners <radius>
+//     segment <x> <y> <x> <y>
+//     arc <x> <y> <radius> <from x> <from y> <to x> <to y>
+//
+// Lines starting with # are comments. The middle of a goal is measured along the wall it is in.
+// Poles are obstacles that bounce the puck back without losing any speed. Every table needs
+// a field, both goals, the puck, the paddles and all three spawn points, and the puck has to
+// fit on the serve spots in the middle of each half. The corners of the field are square
+// unless they are given a radius. Segments and arcs are extra walls inside the field. An arc
+// is given by its center and radius and the directions from the center to its ends, and turns
+// from the first end the way that rotates the x axis onto the y axis.
+
+use vec2::*;
+use game::*;
+use goal::*;
+use collider::*;
+use physics::*;
+use rules::*;
+
+pub static TableVersion: uint = 1;
+
+// The table the game was designed with
+static DefaultTable: &'static str = "airhockey-table 1
+field 640 480
+goal left 240 250 90
+goal right 240 250 90
+pole 0 115 20
+pole 0 365 20
+pole 640 115 20
+pole 640 365 20
+puck 30 1 0.9 0.3
+paddle 40 10 1 0.3
+spawn left 100 240
+spawn right 540 240
+spawn puck 320 240";
+
+pub struct CircleSpec {
+    radius: f64,
+    mass: f64,
+    restitution: f64,
+    friction: f64
+}
+
+// A circle that never moves
+pub struct Obstacle {
+    position: Vec2,
+    radius: f64,
+    restitution: f64
+}
+
+pub struct Table {
+    field: Vec2,
+    // The left goal first, then the right one
+    goals: ~[Goal],
+    obstacles: ~[Obstacle],
+    puck: CircleSpec,
+    paddle: CircleSpec,
+    leftSpawn: Vec2,
+    rightSpawn: Vec2,
+    puckSpawn: Vec2,
+    // The walls around the field and the goals, and the walls inside the field
+    walls: ~[Collider],
+    // The lines the table was read from, so that replays can store it
+    source: ~[~str]
+}
+
+pub fn defaultTable() -> Table {
+    match parseTable(str::lines(DefaultTable)) {
+        Ok(table) => table,
+        Err(errors) => fail!(fmt!("invalid default table: %s", str::connect(errors, ", ")))
+    }
+}
+
+pub fn readTable(path: &Path) -> Result<Table, ~[~str]> {
+    match io::read_whole_file_str(path) {
+        Ok(contents) => parseTable(str::lines(contents)),
+        Err(e) => Err(~[e])
+    }
+}
+
+// Number of values after the keyword of each kind of line, including the name of goals and spawns
+fn valueCount(keyword: &str) -> Option<uint> {
+    match keyword {
+        "field" => Some(2),
+        "goal" => Some(4),
+        "pole" => Some(3),
+        "obstacle" | "puck" | "paddle" => Some(4),
+        "spawn" => Some(3),
+        "corners" => Some(1),
+        "segment" => Some(4),
+        "arc" => Some(7),
+        _ => None
+    }
+}
+
+fn circleSpec(values: &[f64]) -> CircleSpec {
+    CircleSpec { radius: values[0], mass: values[1], restitution: values[2], friction: values[3] }
+}
+
+fn checkCircle(errors: &mut ~[~str], line: uint, name: &str, spec: &CircleSpec) {
+    if spec.radius <= 0. || spec.mass <= 0. {
+        errors.push(fmt!("line %u: the %s needs a positive radius and mass", line, name));
+    }
+    if spec.restitution < 0. || spec.restitution > 1. || spec.friction < 0. {
+        errors.push(fmt!("line %u: the %s needs a restitution between 0 and 1 and a friction of at least 0", line, name));
+    }
+}
+
+// The lowest and the highest end of the mouth of a goal
+fn mouthEnds(goal: &Goal) -> (f64, f64) {
+    let (first, second) = goal.mouth();
+    (f64::min(first.y, second.y), f64::max(first.y, second.y))
+}
+
+// The wall along the left or right side of the field, with an opening for the goal in it
+fn sideWall(x: f64, top: f64, bottom: f64, goal: &Goal) -> ~[Collider] {
+    let (mouthTop, mouthBottom) = mouthEnds(goal);
+    ~[Segment(Vec2(x, top), Vec2(x, mouthTop)),
+      Segment(Vec2(x, mouthBottom), Vec2(x, bottom))]
+}
+
+// Parses a table and checks that the match can be played on it. All problems are
+// reported, each with the number of the line it is on.
+pub fn parseTable(lines: &[~str]) -> Result<Table, ~[~str]> {
+    let header = if lines.is_empty() { ~[] } else { str::words(lines[0]) };
+    if header.len() != 2 || header[0] != ~"airhockey-table" {
+        return Err(~[~"line 1: not a table file"]);
+    }
+    match uint::from_str(header[1]) {
+        Some(version) if version == TableVersion => {}
+        Some(version) => return Err(~[fmt!("line 1: unsupported table version %u", version)]),
+        None => return Err(~[~"line 1: invalid table version"])
+    }
+
+    let mut errors = ~[];
+    // Everything that was read, with the line it is on
+    let mut field = None;
+    let mut puck = None;
+    let mut paddle = None;
+    let mut goals: ~[(Side, f64, f64, f64, uint)] = ~[];
+    let mut obstacles: ~[(Obstacle, uint)] = ~[];
+    let mut spawns: ~[(~str, Vec2, uint)] = ~[];
+    let mut corners = None;
+    let mut walls = ~[];
+    for uint::range(1, lines.len()) |i| {
+        let words = str::words(lines[i]);
+        if words.is_empty() || words[0].starts_with("#") { loop; }
+        let number = i + 1;
+
+        let count = match valueCount(words[0]) {
+            Some(count) => count,
+            None => { errors.push(fmt!("line %u: unknown entry %s", number, words[0])); loop; }
+        };
+        if words.len() != count + 1 {
+            errors.push(fmt!("line %u: %s needs %u values", number, words[0], count));
+            loop;
+        }
+        // Goals and spawns start with a name, everything else is numbers
+        let named = words[0] == ~"goal" || words[0] == ~"spawn";
+        let mut values = ~[];
+        for words.eachi |j, word| {
+            if j == 0 || (named && j == 1) { loop; }
+            match f64::from_str(*word) {
+                Some(x) => values.push(x),
+                None => errors.push(fmt!("line %u: invalid number %s", number, *word))
+            }
+        }
+        if values.len() + (if named { 2 } else { 1 }) != words.len() { loop; }
+
+        match words[0] {
+            ~"field" => {
+                if field.is_some() { errors.push(fmt!("line %u: the field is given twice", number)); }
+                field = Some((Vec2(values[0], values[1]), number));
+            }
+            ~"goal" => {
+                let side = match words[1] {
+                    ~"left" => LeftSide,
+                    ~"right" => RightSide,
+                    _ => { errors.push(fmt!("line %u: unknown goal %s, expected left or right", number, words[1])); loop; }
+                };
+                if goals.any(|&(s, _, _, _, _)| s == side) {
+                    errors.push(fmt!("line %u: the %s goal is given twice", number, words[1]));
+                }
+                goals.push((side, values[0], values[1], values[2], number));
+            }
+            ~"pole" => obstacles.push((Obstacle { position: Vec2(values[0], values[1]), radius: values[2], restitution: 1. }, number)),
+            ~"obstacle" => obstacles.push((Obstacle { position: Vec2(values[0], values[1]), radius: values[2], restitution: values[3] }, number)),
+            ~"puck" => {
+                if puck.is_some() { errors.push(fmt!("line %u: the puck is given twice", number)); }
+                puck = Some((circleSpec(values), number));
+            }
+            ~"paddle" => {
+                if paddle.is_some() { errors.push(fmt!("line %u: the paddle is given twice", number)); }
+                paddle = Some((circleSpec(values), number));
+            }
+            ~"corners" => {
+                if corners.is_some() { errors.push(fmt!("line %u: the corners are given twice", number)); }
+                if values[0] < 0. { errors.push(fmt!("line %u: the corners need a radius of at least 0", number)); }
+                corners = Some((values[0], number));
+            }
+            ~"segment" => walls.push(Segment(Vec2(values[0], values[1]), Vec2(values[2], values[3]))),
+            ~"arc" => {
+                let from = Vec2(values[3], values[4]);
+                let to = Vec2(values[5], values[6]);
+                if values[2] <= 0. || from.length2() == 0. || to.length2() == 0. {
+                    errors.push(fmt!("line %u: arcs need a positive radius and two directions", number));
+                    loop;
+                }
+                walls.push(Arc(Vec2(values[0], values[1]), values[2], from.normalize(), to.normalize()));
+            }
+            // The only entry that is left is a spawn
+            _ => {
+                if words[1] != ~"left" && words[1] != ~"right" && words[1] != ~"puck" {
+                    errors.push(fmt!("line %u: unknown spawn %s, expected left, right or puck", number, words[1]));
+                    loop;
+                }
+                if spawns.any(|&(ref name, _, _)| *name == words[1]) {
+                    errors.push(fmt!("line %u: the %s spawn is given twice", number, words[1]));
+                }
+                spawns.push((copy words[1], Vec2(values[0], values[1]), number));
+            }
+        }
+    }
+
+    let (field, fieldLine) = match field {
+        Some(field) => field,
+        None => { errors.push(~"the table has no field"); return Err(errors); }
+    };
+    if field.x <= 0. || field.y <= 0. {
+        errors.push(fmt!("line %u: the field needs a positive size", fieldLine));
+    }
+    let (puck, puckLine) = match puck {
+        Some(puck) => puck,
+        None => { errors.push(~"the table has no puck"); return Err(errors); }
+    };
+    let (paddle, paddleLine) = match paddle {
+        Some(paddle) => paddle,
+        None => { errors.push(~"the table has no paddle"); return Err(errors); }
+    };
+    checkCircle(&mut errors, puckLine, "puck", &puck);
+    checkCircle(&mut errors, paddleLine, "paddle", &paddle);
+
+    let mut tableGoals = ~[];
+    for [(LeftSide, "left"), (RightSide, "right")].each |&(side, name)| {
+        match goals.find(|&(s, _, _, _, _)| s == side) {
+            Some((_, middle, width, depth, line)) => {
+                if middle - width * 0.5 < 0. || middle + width * 0.5 > field.y {
+                    errors.push(fmt!("line %u: the %s goal does not fit in its wall", line, name));
+                }
+                if width <= puck.radius * 2. {
+                    errors.push(fmt!("line %u: the %s goal is too narrow for the puck", line, name));
+                }
+                // Only a puck that is completely behind the goal line counts, and a puck at full
+                // speed must not get from the goal line through the back wall within one step
+                if depth < puck.radius * 2. + defaultPhysicsParams().puckMaxSpeed * TimeStep {
+                    errors.push(fmt!("line %u: the %s goal is not deep enough to hold the puck", line, name));
+                }
+                tableGoals.push(match side {
+                    LeftSide => Goal(Vec2(0., middle), Vec2(-1., 0.), width, depth, side),
+                    RightSide => Goal(Vec2(field.x, middle), Vec2(1., 0.), width, depth, side)
+                });
+            }
+            None => errors.push(fmt!("the table has no %s goal", name))
+        }
+    }
+
+    // The boundary of the field, open where the goals are, with the corners cut off by arcs
+    let mut tableWalls = ~[];
+    if tableGoals.len() == 2 {
+        let (radius, line) = corners.get_or_default((0., 0));
+        let (left, right) = (&tableGoals[0], &tableGoals[1]);
+        let (leftTop, leftBottom) = mouthEnds(left);
+        let (rightTop, rightBottom) = mouthEnds(right);
+        if radius * 2. > field.x || radius > f64::min(leftTop, rightTop) ||
+           field.y - radius < f64::max(leftBottom, rightBottom) {
+            errors.push(fmt!("line %u: the corners cut into the goals", line));
+        }
+        let (w, h) = (field.x, field.y);
+        tableWalls.push(Segment(Vec2(radius, 0.), Vec2(w - radius, 0.)));
+        tableWalls.push(Segment(Vec2(radius, h), Vec2(w - radius, h)));
+        tableWalls.push_all_move(sideWall(0., radius, h - radius, left));
+        tableWalls.push_all_move(sideWall(w, radius, h - radius, right));
+        if radius > 0. {
+            tableWalls.push(Arc(Vec2(radius, radius), radius, Vec2(-1., 0.), Vec2(0., -1.)));
+            tableWalls.push(Arc(Vec2(w - radius, radius), radius, Vec2(0., -1.), Vec2(1., 0.)));
+            tableWalls.push(Arc(Vec2(w - radius, h - radius), radius, Vec2(1., 0.), Vec2(0., 1.)));
+            tableWalls.push(Arc(Vec2(radius, h - radius), radius, Vec2(0., 1.), Vec2(-1., 0.)));
+        }
+        for tableGoals.each |goal| {
+            tableWalls.push_all_move(goal.walls());
+        }
+    }
+    tableWalls.push_all_move(walls);
+
+    let mut tableObstacles = ~[];
+    for obstacles.each |&(obstacle, line)| {
+        if obstacle.radius <= 0. || obstacle.restitution < 0. || obstacle.restitution > 1. {
+            errors.push(fmt!("line %u: obstacles need a positive radius and a restitution between 0 and 1", line));
+        }
+        tableObstacles.push(obstacle);
+    }
+
+    let mut spawnPositions = ~[];
+    for [("left", paddle.radius), ("right", paddle.radius), ("puck", puck.radius)].each |&(name, radius)| {
+        match spawns.find(|&(ref n, _, _)| *n == name.to_owned()) {
+            Some((_, position, line)) => {
+                if position.x < radius || position.x > field.x - radius ||
+                   position.y < radius || position.y > field.y - radius {
+                    errors.push(fmt!("line %u: the %s spawn is not inside the field", line, name));
+                }
+                if (name == "left" && position.x > field.x * 0.5) || (name == "right" && position.x < field.x * 0.5) {
+                    errors.push(fmt!("line %u: the %s spawn is not on its own half", line, name));
+                }
+                if tableObstacles.any(|o| (o.position - position).length() < o.radius + radius) ||
+                   tableWalls.any(|wall| wall.contact(position, radius).is_some()) {
+                    errors.push(fmt!("line %u: the %s spawn overlaps an obstacle or a wall", line, name));
+                }
+                spawnPositions.push(position);
+            }
+            None => errors.push(fmt!("the table has no %s spawn", name))
+        }
+    }
+
+    // A serve puts the puck anywhere on a short vertical line in the middle of each half
+    for [(LeftSide, "left"), (RightSide, "right")].each |&(side, name)| {
+        let top = serveSpot(field, side, -ServeSpread);
+        let bottom = serveSpot(field, side, ServeSpread);
+        let serves = Segment(top, bottom);
+        if top.y < puck.radius || bottom.y > field.y - puck.radius ||
+           tableObstacles.any(|o| (serves.closestPoint(o.position) - o.position).length() < o.radius + puck.radius) ||
+           tableWalls.any(|wall| wall.contact(top, puck.radius).is_some() || wall.sweep(top, bottom, puck.radius).is_some()) {
+            errors.push(fmt!("the puck does not fit where the %s side serves", name));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Table {
+        field: field,
+        goals: tableGoals,
+        obstacles: tableObstacles,
+        puck: puck,
+        paddle: paddle,
+        leftSpawn: spawnPositions[0],
+        rightSpawn: spawnPositions[1],
+        puckSpawn: spawnPositions[2],
+        walls: tableWalls,
+        source: vec::from_slice(lines)
+    })
+}
+
+// Errors in the default table with some lines replaced, lines past its end are appended
+#[cfg(test)]
+fn tableErrors(changes: &[(uint, &str)]) -> ~[~str] {
+    let mut lines = str::lines(DefaultTable);
+    for changes.each |&(index, line)| {
+        if index < lines.len() { lines[index] = line.to_owned(); } else { lines.push(line.to_owned()); }
+    }
+    match parseTable(lines) {
+        Ok(_) => ~[],
+        Err(errors) => errors
+    }
+}
+
+#[cfg(test)]
+fn reports(errors: &[~str], message: &str) -> bool {
+    vec::contains(errors, &message.to_owned())
+}
+
+#[test]
+fn defaultTableIsValid() {
+    assert!(tableErrors([]).is_empty());
+}
+
+#[test]
+fn duplicateGoal() {
+    let errors = tableErrors([(3, "goal left 240 250 90")]);
+    assert!(reports(errors, "line 4: the left goal is given twice"));
+    assert!(reports(errors, "the table has no right goal"));
+}
+
+#[test]
+fn shallowGoal() {
+    // The puck needs 60 and covers 15 in a step
+    assert!(tableErrors([(2, "goal left 240 250 75")]).is_empty());
+    let errors = tableErrors([(2, "goal left 240 250 74")]);
+    assert!(reports(errors, "line 3: the left goal is not deep enough to hold the puck"));
+}
+
+#[test]
+fn unknownEntry() {
+    let errors = tableErrors([(13, "bumper 1 2 3")]);
+    assert!(reports(errors, "line 14: unknown entry bumper"));
+}
+
+#[test]
+fn missingValues() {
+    let errors = tableErrors([(5, "pole 0 365")]);
+    assert!(reports(errors, "line 6: pole needs 3 values"));
+}
+
+#[test]
+fn invalidNumber() {
+    let errors = tableErrors([(4, "pole 0 1x5 20")]);
+    assert!(reports(errors, "line 5: invalid number 1x5"));
+}
+
+#[test]
+fn missingSpawn() {
+    let errors = tableErrors([(12, "# spawn puck 320 240")]);
+    assert!(reports(errors, "the table has no puck spawn"));
+}
+
+#[test]
+fn blockedServe() {
+    // Right below the middle of the left half, where the puck can be served
+    let errors = tableErrors([(13, "obstacle 160 300 10 1")]);
+    assert!(errors == ~[~"the puck does not fit where the left side serves"]);
+}
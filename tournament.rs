@@ -0,0 +1,231 @@
+extern mod airhockeysim;
+
+use airhockeysim::game::*;
+use airhockeysim::controller::*;
+use airhockeysim::ai::*;
+use airhockeysim::options::*;
+
+// Rating every entrant starts with
+static InitialElo: f64 = 1500.;
+// How far a single match can move a rating
+static EloFactor: f64 = 16.;
+
+// One AI profile taking part in the tournament, with its results so far
+struct Entrant {
+    level: AiLevel,
+    style: AiStyle,
+    wins: uint,
+    draws: uint,
+    losses: uint,
+    goalsFor: uint,
+    goalsAgainst: uint,
+    elo: f64
+}
+
+fn Entrant(level: AiLevel, style: AiStyle) -> Entrant {
+    Entrant { level: level, style: style, wins: 0, draws: 0, losses: 0, goalsFor: 0, goalsAgainst: 0, elo: InitialElo }
+}
+
+pub impl Entrant {
+    fn name(&self) -> ~str {
+        fmt!("%s/%s", aiLevelName(self.level), aiStyleName(self.style))
+    }
+    fn played(&self) -> uint {
+        self.wins + self.draws + self.losses
+    }
+    // Swiss standings: a win counts two points, a draw one
+    fn points(&self) -> uint {
+        self.wins * 2 + self.draws
+    }
+    fn goalDifference(&self) -> int {
+        (self.goalsFor as int) - (self.goalsAgainst as int)
+    }
+}
+
+// Every AI level in every style
+fn allEntrants() -> ~[Entrant] {
+    let mut entrants = ~[];
+    for allAiLevels().each |&level| {
+        for [Classic, Predictive].each |&style| {
+            entrants.push(Entrant(level, style));
+        }
+    }
+    entrants
+}
+
+struct Tournament {
+    entrants: ~[Entrant],
+    // Matches played between every pairing
+    matches: uint,
+    // Length of a match in steps
+    steps: uint,
+    // Every match gets its own seed, so a tournament can be repeated exactly. The seed decides
+    // the face-off and the serves, so the matches of a pairing do not all play out the same.
+    nextSeed: u64
+}
+
+pub impl Tournament {
+    // Plays a match between two entrants, switching sides every other match, and records the result
+    fn playMatch(&mut self, a: uint, b: uint, swapSides: bool) {
+        let seed = self.nextSeed;
+        self.nextSeed += 1;
+
+        let (left, right) = if swapSides { (b, a) } else { (a, b) };
+        let mut game = setupGame(seed);
+        let mut leftController = ~AiController(self.entrants[left].level, self.entrants[left].style, seed) as ~Controller;
+        let mut rightController = ~AiController(self.entrants[right].level, self.entrants[right].style, seed + 1) as ~Controller;
+        let devices = Devices();
+        // The match ends when one side has won under the default rules, or when the time is up
+        for uint::range(0, self.steps) |_| {
+            stepControllers(game, &mut leftController, &mut rightController, &devices);
+            if game.clock.over { break; }
+        }
+
+        self.recordResult(left, right, game.playerScore, game.opponentScore);
+    }
+
+    fn recordResult(&mut self, a: uint, b: uint, goalsA: uint, goalsB: uint) {
+        let score = if goalsA > goalsB { 1. } else if goalsA < goalsB { 0. } else { 0.5 };
+        let expected = 1. / (1. + f64::pow(10., (self.entrants[b].elo - self.entrants[a].elo) / 400.));
+        let change = EloFactor * (score - expected);
+        self.entrants[a].elo += change;
+        self.entrants[b].elo -= change;
+
+        self.entrants[a].goalsFor += goalsA;
+        self.entrants[a].goalsAgainst += goalsB;
+        self.entrants[b].goalsFor += goalsB;
+        self.entrants[b].goalsAgainst += goalsA;
+        if goalsA > goalsB {
+            self.entrants[a].wins += 1;
+            self.entrants[b].losses += 1;
+        } else if goalsA < goalsB {
+            self.entrants[a].losses += 1;
+            self.entrants[b].wins += 1;
+        } else {
+            self.entrants[a].draws += 1;
+            self.entrants[b].draws += 1;
+        }
+    }
+
+    fn playPairing(&mut self, a: uint, b: uint) {
+        for uint::range(0, self.matches) |index| {
+            self.playMatch(a, b, index % 2 == 1);
+        }
+    }
+
+    // Every entrant meets every other entrant
+    fn roundRobin(&mut self) {
+        let count = self.entrants.len();
+        for uint::range(0, count) |a| {
+            for uint::range(a + 1, count) |b| {
+                self.playPairing(a, b);
+            }
+        }
+    }
+
+    // Entrants with similar standings meet each other, avoiding rematches where possible.
+    // With an odd number of entrants the lowest one that is left over sits the round out.
+    fn swiss(&mut self, rounds: uint) {
+        let mut met: ~[(uint, uint)] = ~[];
+        for uint::range(0, rounds) |_| {
+            let order = self.ranking(|e| (e.points() as f64) * 10000. + e.elo);
+            let mut paired = vec::from_elem(order.len(), false);
+            for uint::range(0, order.len()) |i| {
+                let a = order[i];
+                if paired[i] { loop; }
+                // The closest entrant not met before, or else simply the closest one
+                let mut opponent = None;
+                for uint::range(i + 1, order.len()) |j| {
+                    if !paired[j] && !vec::contains(met, &(a, order[j])) {
+                        opponent = Some(j);
+                        break;
+                    }
+                }
+                if opponent.is_none() {
+                    for uint::range(i + 1, order.len()) |j| {
+                        if !paired[j] {
+                            opponent = Some(j);
+                            break;
+                        }
+                    }
+                }
+                match opponent {
+                    Some(j) => {
+                        let b = order[j];
+                        paired[i] = true;
+                        paired[j] = true;
+                        met.push((a, b));
+                        met.push((b, a));
+                        self.playPairing(a, b);
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    // Indices of the entrants, from the highest key to the lowest
+    fn ranking(&self, key: &fn(&Entrant) -> f64) -> ~[uint] {
+        let mut order = vec::from_fn(self.entrants.len(), |i| i);
+        for uint::range(1, order.len()) |i| {
+            let mut j = i;
+            while j > 0 && key(&self.entrants[order[j - 1]]) < key(&self.entrants[order[j]]) {
+                order[j - 1] <-> order[j];
+                j -= 1;
+            }
+        }
+        order
+    }
+
+    fn printTable(&self) {
+        io::println(fmt!("%-18s %6s %5s %5s %5s %6s %5s %6s", "AI", "played", "won", "drawn", "lost", "win %", "goals", "elo"));
+        for self.ranking(|e| e.elo).each |&i| {
+            let e = &self.entrants[i];
+            let winRate = if e.played() > 0 { (e.wins as f64) / (e.played() as f64) * 100. } else { 0. };
+            io::println(fmt!("%-18s %6u %5u %5u %5u %6.1f %+5d %6.0f",
+                e.name(), e.played(), e.wins, e.draws, e.losses, winRate, e.goalDifference(), e.elo));
+        }
+    }
+}
+
+// Plays a tournament between all AI profiles without any graphics.
+// Usage: tournament [--format round-robin|swiss] [--matches <per pairing>]
+//                   [--seconds <per match>] [--rounds <swiss rounds>] [--seed <seed>]
+fn main() {
+    let args = os::args();
+    let matches = match parseOption(args, "--matches", 10, |s| uint::from_str(s)) {
+        Ok(matches) => matches,
+        Err(e) => { io::println(e); return; }
+    };
+    let seconds = match parseOption(args, "--seconds", 60., |s| f64::from_str(s)) {
+        Ok(seconds) => seconds,
+        Err(e) => { io::println(e); return; }
+    };
+    let seed = match parseOption(args, "--seed", 1, |s| u64::from_str(s)) {
+        Ok(seed) => seed,
+        Err(e) => { io::println(e); return; }
+    };
+
+    let mut tournament = Tournament {
+        entrants: allEntrants(),
+        matches: matches,
+        steps: (seconds / TimeStep) as uint,
+        nextSeed: seed
+    };
+
+    match getOption(args, "--format").get_or_default(~"round-robin") {
+        ~"round-robin" => tournament.roundRobin(),
+        ~"swiss" => {
+            match parseOption(args, "--rounds", 3, |s| uint::from_str(s)) {
+                Ok(rounds) => tournament.swiss(rounds),
+                Err(e) => { io::println(e); return; }
+            }
+        }
+        format => {
+            io::println(fmt!("Unknown format %s, expected round-robin or swiss", format));
+            return;
+        }
+    }
+
+    tournament.printTable();
+}
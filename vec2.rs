@@ -1,8 +1,8 @@
 use core::vec::*;
 
-pub struct Vec2 { x:float, y:float }
+pub struct Vec2 { x:f64, y:f64 }
 
-pub fn Vec2(x:float, y:float) -> Vec2 { Vec2 { x: x, y: y } }
+pub fn Vec2(x:f64, y:f64) -> Vec2 { Vec2 { x: x, y: y } }
 
 pub static Zero:Vec2 = Vec2 { x: 0., y: 0. };
 pub static One:Vec2 = Vec2 { x: 1., y: 1. };
@@ -21,14 +21,14 @@ impl Sub<Vec2,Vec2> for Vec2 {
     }
 }
 
-impl Mul<float,Vec2> for Vec2 {
-    fn mul(&self, rhs: &float) -> Vec2 {
+impl Mul<f64,Vec2> for Vec2 {
+    fn mul(&self, rhs: &f64) -> Vec2 {
         Vec2 {x: self.x * (*rhs), y: self.y * (*rhs)}
     }
 }
 
-impl Div<float,Vec2> for Vec2 {
-    fn div(&self, rhs: &float) -> Vec2 {
+impl Div<f64,Vec2> for Vec2 {
+    fn div(&self, rhs: &f64) -> Vec2 {
         Vec2 {x: self.x / (*rhs), y: self.y / (*rhs)}
     }
 }
@@ -40,10 +40,10 @@ impl Neg<Vec2> for Vec2 {
 }
 
 pub impl Vec2 {
-    fn length(&self) -> float {
-        float::sqrt(self.length2())
+    fn length(&self) -> f64 {
+        f64::sqrt(self.length2())
     }
-    fn length2(&self) -> float {
+    fn length2(&self) -> f64 {
         self.x * self.x + self.y * self.y
     }
     fn normalize(&self) -> Vec2 {
@@ -59,9 +59,12 @@ pub impl Vec2 {
     fn normalizeOrZero(&self) -> Vec2 {
         self.normalizeOr(Zero)
     }
-    fn dot(&self, b: Vec2) -> float {
+    fn dot(&self, b: Vec2) -> f64 {
         self.x * b.x + self.y * b.y
     }
+    fn cross(&self, b: Vec2) -> f64 {
+        self.x * b.y - self.y * b.x
+    }
     fn normalRight(&self) -> Vec2 {
         Vec2 { x: -self.y, y: self.x }
     }